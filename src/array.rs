@@ -0,0 +1,155 @@
+//! Wrappers that map to NBT's array types with serde. A plain `Vec<i32>`
+//! accepts an int array or a list of ints and is written as a list;
+//! `IntArray` only accepts and writes the former. The modules of the same
+//! names do the same for plain vectors with `#[serde(with = "...")]`.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+pub(crate) const BYTE_ARRAY: &str = "__nbt_byte_array";
+pub(crate) const INT_ARRAY: &str = "__nbt_int_array";
+pub(crate) const LONG_ARRAY: &str = "__nbt_long_array";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ByteArray(pub Vec<u8>);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct IntArray(pub Vec<i32>);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LongArray(pub Vec<i64>);
+
+/// Reads the elements of an array, whether the deserializer hands them over
+/// directly or wrapped as a newtype.
+struct ArrayVisitor<T>(&'static str, PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for ArrayVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Vec<T>, D::Error> {
+        Vec::deserialize(d)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let mut out = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(x) = seq.next_element()? {
+            out.push(x);
+        }
+        Ok(out)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteArray {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct Bytes;
+
+        impl<'de> Visitor<'de> for Bytes {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a byte array")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Vec<u8>, D::Error> {
+                d.deserialize_byte_buf(self)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(v)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Vec<u8>, A::Error> {
+                let bytes: Vec<i8> = ArrayVisitor("a byte array", PhantomData).visit_seq(seq)?;
+                Ok(bytes.into_iter().map(|b| b as u8).collect())
+            }
+        }
+
+        d.deserialize_newtype_struct(BYTE_ARRAY, Bytes).map(ByteArray)
+    }
+}
+
+impl<'de> Deserialize<'de> for IntArray {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        d.deserialize_newtype_struct(INT_ARRAY, ArrayVisitor("an int array", PhantomData)).map(IntArray)
+    }
+}
+
+impl<'de> Deserialize<'de> for LongArray {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        d.deserialize_newtype_struct(LONG_ARRAY, ArrayVisitor("a long array", PhantomData)).map(LongArray)
+    }
+}
+
+/// Serializes as bytes rather than a sequence of numbers.
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_bytes(self.0)
+    }
+}
+
+impl Serialize for ByteArray {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct(BYTE_ARRAY, &Bytes(&self.0))
+    }
+}
+
+impl Serialize for IntArray {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct(INT_ARRAY, &self.0)
+    }
+}
+
+impl Serialize for LongArray {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct(LONG_ARRAY, &self.0)
+    }
+}
+
+pub mod byte_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(x: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct(BYTE_ARRAY, &Bytes(x))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        ByteArray::deserialize(d).map(|a| a.0)
+    }
+}
+
+pub mod int_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(x: &[i32], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct(INT_ARRAY, x)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<i32>, D::Error> {
+        IntArray::deserialize(d).map(|a| a.0)
+    }
+}
+
+pub mod long_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(x: &[i64], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_newtype_struct(LONG_ARRAY, x)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<i64>, D::Error> {
+        LongArray::deserialize(d).map(|a| a.0)
+    }
+}
@@ -0,0 +1,197 @@
+//! Biomes, kept per section in a palette of 4×4×4 cells from 1.18, and in an
+//! array of ids for the whole chunk before.
+
+use crate::chunk::{bits, field, malformed, Packing};
+use crate::error::Error;
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+
+/// Cells in a section.
+const LEN: usize = 4 * 4 * 4;
+
+/// The biomes of a 1.18 section as entries of a palette of biome names,
+/// such as `minecraft:plains`. Cells are ordered by y, then z, then x.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Biomes {
+    pub palette: Vec<String>,
+    /// For each cell, its entry in `palette`.
+    pub indices: Vec<u8>,
+}
+
+impl Biomes {
+    /// Unpacks `data`, which takes as many bits per cell as the palette
+    /// needs, so none for a palette of one entry.
+    pub fn decode(palette: Vec<String>, data: &[i64]) -> Result<Self, Error> {
+        let indices = Packing::Padded.unpack(data, bits(palette.len()), LEN).ok_or_else(|| malformed("data"))?;
+        if palette.is_empty() || indices.iter().any(|&i| i as usize >= palette.len()) {
+            return Err(malformed("data"));
+        }
+        Ok(Self { palette, indices: indices.into_iter().map(|i| i as u8).collect() })
+    }
+
+    /// Reads the `biomes` of a section, or `None` if it has none.
+    pub fn from_section(section: &Payload) -> Result<Option<Self>, Error> {
+        let Some(biomes) = field(section, "biomes") else { return Ok(None) };
+        let palette = match field(biomes, "palette") {
+            Some(Payload::List(_, entries)) => entries.iter().map(|p| match p {
+                Payload::String(name) => Ok(name.clone()),
+                _ => Err(malformed("biomes.palette")),
+            }),
+            _ => return Err(malformed("biomes.palette")),
+        };
+        let palette = palette.collect::<Result<_, _>>()?;
+        let data = match field(biomes, "data") {
+            Some(Payload::LongArray(data)) => data,
+            None => &[][..],
+            Some(_) => return Err(malformed("biomes.data")),
+        };
+        Self::decode(palette, data).map(Some).map_err(|_| malformed("biomes.data"))
+    }
+
+    /// Gathers the distinct biomes of `cells`, in the order above, into a
+    /// palette in the order they first appear. Fails unless there are
+    /// exactly 64 cells.
+    pub fn from_cells<I: IntoIterator<Item = String>>(cells: I) -> Result<Self, Error> {
+        let mut palette: Vec<String> = Vec::new();
+        let mut index = |biome| match palette.iter().position(|p| *p == biome) {
+            Some(i) => i as u8,
+            None => {
+                palette.push(biome);
+                (palette.len() - 1) as u8
+            }
+        };
+        let indices: Vec<_> = cells.into_iter().take(LEN + 1).map(&mut index).collect();
+        if indices.len() != LEN {
+            return Err(malformed(""));
+        }
+        Ok(Self { palette, indices })
+    }
+
+    /// The biome at block coordinates `x`, `y`, `z`, taken modulo 16.
+    pub fn get(&self, x: i32, y: i32, z: i32) -> &str {
+        let i = ((y & 15) >> 2) << 4 | ((z & 15) >> 2) << 2 | (x & 15) >> 2;
+        &self.palette[self.indices[i as usize] as usize]
+    }
+
+    /// Packs the indices into as few bits as the palette allows.
+    pub fn encode(&self) -> Vec<i64> {
+        let indices: Vec<_> = self.indices.iter().map(|&i| i as u32).collect();
+        Packing::Padded.pack(&indices, bits(self.palette.len()))
+    }
+
+    /// The `biomes` compound of a section.
+    pub fn to_payload(&self) -> Payload {
+        let palette = self.palette.iter().map(|name| Payload::String(name.clone())).collect();
+        let mut tags = vec![Tag::new("palette", Payload::list(palette))];
+        if self.palette.len() > 1 {
+            tags.push(Tag::new("data", Payload::LongArray(self.encode())));
+        }
+        Payload::Compound(tags)
+    }
+}
+
+/// The numeric biome ids of a whole chunk, from its `Biomes` array, before
+/// 1.18.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegacyBiomes {
+    /// Before 1.13: a byte per column, ordered by z, then x.
+    Bytes(Vec<u8>),
+    /// 1.13 and 1.14: an int per column.
+    Columns(Vec<i32>),
+    /// 1.15 to 1.17: an int per 4×4×4 cell from the bottom of the world,
+    /// ordered by y, then z, then x; 1024 for a world 256 blocks high.
+    Cells(Vec<i32>),
+}
+
+impl LegacyBiomes {
+    /// Reads a `Biomes` array, telling layouts apart by their length.
+    pub fn from_payload(biomes: &Payload) -> Result<Self, Error> {
+        match biomes {
+            Payload::ByteArray(ids) if ids.len() == 256 => Ok(Self::Bytes(ids.clone())),
+            Payload::IntArray(ids) if ids.len() == 256 => Ok(Self::Columns(ids.clone())),
+            Payload::IntArray(ids) if !ids.is_empty() && ids.len() % 16 == 0 => Ok(Self::Cells(ids.clone())),
+            _ => Err(malformed("Biomes")),
+        }
+    }
+
+    /// The biome at `x`, `z`, taken modulo 16, and `y` blocks above the
+    /// bottom of the world. Cells above or below the array repeat its top or
+    /// bottom layer, as vanilla does.
+    pub fn get(&self, x: i32, y: i32, z: i32) -> i32 {
+        let column = ((z & 15) << 4 | (x & 15)) as usize;
+        match self {
+            Self::Bytes(ids) => ids[column] as i32,
+            Self::Columns(ids) => ids[column],
+            Self::Cells(ids) => {
+                let layer = (y >> 2).clamp(0, (ids.len() / 16) as i32 - 1);
+                ids[(layer << 4 | ((z & 15) >> 2) << 2 | (x & 15) >> 2) as usize]
+            }
+        }
+    }
+
+    pub fn to_payload(&self) -> Payload {
+        match self {
+            Self::Bytes(ids) => Payload::ByteArray(ids.clone()),
+            Self::Columns(ids) | Self::Cells(ids) => Payload::IntArray(ids.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Reason;
+    use crate::snbt::parse;
+
+    #[test]
+    fn section() {
+        // A river through the middle of the lower half
+        let cells = (0..LEN).map(|i| match (i % 4, i / 16) {
+            (1 | 2, 0 | 1) => "minecraft:river".to_string(),
+            _ => "minecraft:plains".to_string(),
+        });
+        let biomes = Biomes::from_cells(cells.clone()).unwrap();
+        assert_eq!(biomes.palette, ["minecraft:plains", "minecraft:river"]);
+        assert_eq!((biomes.get(5, 3, 15), biomes.get(5, 8, 15)), ("minecraft:river", "minecraft:plains"));
+        assert_eq!(biomes.get(-1, 0, 0), "minecraft:plains");
+        assert_eq!(biomes.encode(), [0x6666_6666]);
+        assert!(Biomes::from_cells(cells.clone().take(LEN - 1)).is_err());
+        assert!(Biomes::from_cells(cells.chain(["minecraft:ocean".to_string()])).is_err());
+
+        let section = Payload::Compound(vec![Tag::new("biomes", biomes.to_payload())]);
+        assert_eq!(Biomes::from_section(&section).unwrap(), Some(biomes));
+
+        let single = parse(r#"{biomes:{palette:["minecraft:ocean"]}}"#).unwrap();
+        let single = Biomes::from_section(&single).unwrap().unwrap();
+        assert_eq!((single.get(0, 0, 0), single.encode().len()), ("minecraft:ocean", 0));
+        assert_eq!(Biomes::from_section(&parse("{Y:0b}").unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn legacy() {
+        let mut columns = vec![1; 256];
+        columns[5 * 16 + 3] = 7;
+        let biomes = LegacyBiomes::from_payload(&Payload::IntArray(columns.clone())).unwrap();
+        assert_eq!((biomes.get(3, 100, 5), biomes.get(19, 0, -11)), (7, 7));
+        assert_eq!(biomes.to_payload(), Payload::IntArray(columns));
+
+        let bytes = LegacyBiomes::from_payload(&Payload::ByteArray(vec![200; 256])).unwrap();
+        assert_eq!((&bytes, bytes.get(0, 0, 0)), (&LegacyBiomes::Bytes(vec![200; 256]), 200));
+
+        let cells: Vec<i32> = (0..1024).map(|i| i / 16).collect();
+        let biomes = LegacyBiomes::from_payload(&Payload::IntArray(cells.clone())).unwrap();
+        assert_eq!([-8, 0, 7, 255, 300].map(|y| biomes.get(0, y, 0)), [0, 0, 1, 63, 63]);
+        assert_eq!(biomes.to_payload(), Payload::IntArray(cells));
+    }
+
+    #[test]
+    fn errors() {
+        let e = LegacyBiomes::from_payload(&Payload::IntArray(vec![0; 100])).unwrap_err();
+        assert_eq!((e.reason(), e.path().to_string()), (&Reason::Malformed, "Biomes".into()));
+        let bad = parse(r#"{biomes:{palette:["a","b"],data:[L;]}}"#).unwrap();
+        assert_eq!(Biomes::from_section(&bad).unwrap_err().path().to_string(), "biomes.data");
+        let bad = parse(r#"{biomes:{palette:[1]}}"#).unwrap();
+        assert_eq!(Biomes::from_section(&bad).unwrap_err().path().to_string(), "biomes.palette");
+        assert!(Biomes::decode(vec!["a".into(), "b".into(), "c".into()], &[3]).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! The block states of a section: a palette, and which entry of it each
+//! block is.
+
+use crate::chunk::{bits, field, malformed, Packing};
+use crate::error::Error;
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+
+/// Blocks in a section.
+const LEN: usize = 16 * 16 * 16;
+
+/// The blocks of a 16×16×16 section as entries of a palette, which are
+/// usually `{Name, Properties}` compounds. Blocks are ordered by y, then z,
+/// then x, and coordinates are taken modulo 16.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockStates {
+    pub palette: Vec<Payload>,
+    /// For each block, its entry in `palette`.
+    pub indices: Vec<u16>,
+}
+
+impl BlockStates {
+    /// Unpacks `data`, which takes as many bits per block as the palette
+    /// needs but at least 4. A palette of one entry needs no data.
+    pub fn decode(palette: Vec<Payload>, data: &[i64], packing: Packing) -> Result<Self, Error> {
+        let indices = match (palette.len(), data) {
+            (1, []) => vec![0; LEN],
+            (n, _) => packing.unpack(data, bits(n).max(4), LEN).ok_or_else(|| malformed("data"))?,
+        };
+        if indices.iter().any(|&i| i as usize >= palette.len()) {
+            return Err(malformed("data"));
+        }
+        Ok(Self { palette, indices: indices.into_iter().map(|i| i as u16).collect() })
+    }
+
+    /// Reads the blocks of a section, kept in `block_states` from 1.18 and
+    /// in `Palette` and `BlockStates` before. Sections without blocks give
+    /// `None`.
+    pub fn from_section(section: &Payload, packing: Packing) -> Result<Option<Self>, Error> {
+        let legacy = (field(section, "Palette"), field(section, "BlockStates"));
+        let (palette, data, [palette_name, data_name]) = match field(section, "block_states") {
+            Some(states) => (field(states, "palette"), field(states, "data"), ["block_states.palette", "block_states.data"]),
+            None if legacy == (None, None) => return Ok(None),
+            None => (legacy.0, legacy.1, ["Palette", "BlockStates"]),
+        };
+        let palette = match palette {
+            Some(Payload::List(_, entries)) => entries.clone(),
+            _ => return Err(malformed(palette_name)),
+        };
+        let data = match data {
+            Some(Payload::LongArray(data)) => data,
+            None if palette.len() == 1 => &[][..],
+            _ => return Err(malformed(data_name)),
+        };
+        Self::decode(palette, data, packing).map(Some).map_err(|_| malformed(data_name))
+    }
+
+    /// Gathers the distinct blocks of `blocks`, in the order above, into a
+    /// palette in the order they first appear. Fails unless there are
+    /// exactly 4096 blocks.
+    pub fn from_blocks<I: IntoIterator<Item = Payload>>(blocks: I) -> Result<Self, Error> {
+        let mut palette: Vec<Payload> = Vec::new();
+        let mut index = |block| match palette.iter().position(|p| *p == block) {
+            Some(i) => i as u16,
+            None => {
+                palette.push(block);
+                (palette.len() - 1) as u16
+            }
+        };
+        let indices: Vec<_> = blocks.into_iter().take(LEN + 1).map(&mut index).collect();
+        if indices.len() != LEN {
+            return Err(malformed(""));
+        }
+        Ok(Self { palette, indices })
+    }
+
+    pub fn get(&self, x: i32, y: i32, z: i32) -> &Payload {
+        &self.palette[self.indices[index(x, y, z)] as usize]
+    }
+
+    /// Packs the indices into as few bits as the palette allows, at least 4,
+    /// or none for a palette of one entry.
+    pub fn encode(&self, packing: Packing) -> Vec<i64> {
+        match self.palette.len() {
+            1 => Vec::new(),
+            n => packing.pack(&self.indices.iter().map(|&i| i as u32).collect::<Vec<_>>(), bits(n).max(4)),
+        }
+    }
+
+    /// The `block_states` compound of a 1.18 section.
+    pub fn to_payload(&self, packing: Packing) -> Payload {
+        let mut tags = vec![Tag::new("palette", Payload::list(self.palette.clone()))];
+        if self.palette.len() > 1 {
+            tags.push(Tag::new("data", Payload::LongArray(self.encode(packing))));
+        }
+        Payload::Compound(tags)
+    }
+}
+
+fn index(x: i32, y: i32, z: i32) -> usize {
+    ((y & 15) << 8 | (z & 15) << 4 | (x & 15)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Reason;
+    use crate::snbt::parse;
+
+    fn block(name: &str) -> Payload {
+        Payload::Compound(vec![Tag::new("Name", Payload::String(name.into()))])
+    }
+
+    /// Stone with a layer of dirt at y = 1 and grass at x = 3, y = 2, z = 5.
+    fn blocks() -> Vec<Payload> {
+        (0..LEN)
+            .map(|i| match (i % 16, i / 256, i / 16 % 16) {
+                (3, 2, 5) => block("minecraft:grass_block"),
+                (_, 1, _) => block("minecraft:dirt"),
+                _ => block("minecraft:stone"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let states = BlockStates::from_blocks(blocks()).unwrap();
+        assert_eq!(states.palette.len(), 3);
+        assert_eq!(states.get(3, 2, 5), &block("minecraft:grass_block"));
+        assert_eq!(states.get(7, 17, 0), &block("minecraft:dirt"));
+        assert_eq!(states.encode(Packing::Padded).len(), 256);
+
+        for packing in [Packing::Spanning, Packing::Padded] {
+            let section = Payload::Compound(vec![Tag::new("block_states", states.to_payload(packing))]);
+            assert_eq!(BlockStates::from_section(&section, packing).unwrap(), Some(states.clone()));
+            let legacy = Payload::Compound(vec![
+                Tag::new("Palette", Payload::list(states.palette.clone())),
+                Tag::new("BlockStates", Payload::LongArray(states.encode(packing))),
+            ]);
+            assert_eq!(BlockStates::from_section(&legacy, packing).unwrap(), Some(states.clone()));
+        }
+
+        // Every block of the section must be given, and no more
+        assert!(BlockStates::from_blocks(blocks().into_iter().take(LEN - 1)).is_err());
+        assert!(BlockStates::from_blocks(blocks().into_iter().chain([block("minecraft:air")])).is_err());
+    }
+
+    #[test]
+    fn bits_per_block() {
+        // 17 entries take 5 bits, so values cross longs unless padded
+        let many: Vec<_> = (0..LEN).map(|i| block(&format!("b{}", i % 17))).collect();
+        let states = BlockStates::from_blocks(many).unwrap();
+        assert_eq!(states.encode(Packing::Spanning).len(), 320);
+        assert_eq!(states.encode(Packing::Padded).len(), 342);
+        let data = states.encode(Packing::Spanning);
+        assert_eq!(BlockStates::decode(states.palette.clone(), &data, Packing::Spanning).unwrap(), states);
+        assert!(BlockStates::decode(states.palette.clone(), &data, Packing::Padded).is_err());
+
+        let air = parse("{block_states:{palette:[{Name:\"minecraft:air\"}]}}").unwrap();
+        let air = BlockStates::from_section(&air, Packing::Padded).unwrap().unwrap();
+        assert_eq!(air.indices, [0; LEN]);
+        assert_eq!(air.to_payload(Packing::Padded), parse("{palette:[{Name:\"minecraft:air\"}]}").unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        let empty = parse("{Y:0b,SkyLight:[B;]}").unwrap();
+        assert_eq!(BlockStates::from_section(&empty, Packing::Padded).unwrap(), None);
+
+        let short = parse("{block_states:{palette:[{Name:a},{Name:b}],data:[L;1L]}}").unwrap();
+        let e = BlockStates::from_section(&short, Packing::Padded).unwrap_err();
+        assert_eq!((e.reason(), e.path().to_string()), (&Reason::Malformed, "block_states.data".into()));
+
+        // Index 2 of a palette of two
+        let mut data = vec![0; 256];
+        data[0] = 2;
+        let e = BlockStates::decode(vec![block("a"), block("b")], &data, Packing::Padded).unwrap_err();
+        assert_eq!(e.path().to_string(), "data");
+        let missing = parse("{Palette:[{Name:a},{Name:b}]}").unwrap();
+        assert_eq!(BlockStates::from_section(&missing, Packing::Spanning).unwrap_err().path().to_string(), "BlockStates");
+    }
+}
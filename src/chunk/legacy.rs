@@ -0,0 +1,154 @@
+//! Blocks as numeric ids and metadata, as stored before 1.13 replaced them
+//! with block states.
+
+use crate::chunk::{field, malformed, NibbleArray};
+use crate::error::Error;
+use crate::parser::payload::Payload;
+
+/// Blocks in a section.
+const LEN: usize = 16 * 16 * 16;
+/// Height of an MCRegion chunk, which is not split into sections.
+const MCREGION_HEIGHT: usize = 128;
+
+/// The blocks of a 16×16×16 section as ids, such as 35 for wool, and
+/// metadata, such as its colour. Blocks are ordered by y, then z, then x,
+/// and coordinates are taken modulo 16.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyBlocks {
+    /// Ids of up to 12 bits, the highest 4 from `Add`.
+    pub ids: Vec<u16>,
+    /// Metadata of 4 bits.
+    pub data: Vec<u8>,
+}
+
+impl LegacyBlocks {
+    /// Reads the `Blocks`, `Add` and `Data` of an Anvil section, or `None`
+    /// if it has no blocks.
+    pub fn from_section(section: &Payload) -> Result<Option<Self>, Error> {
+        let blocks = match field(section, "Blocks") {
+            Some(Payload::ByteArray(blocks)) if blocks.len() == LEN => blocks,
+            Some(_) => return Err(malformed("Blocks")),
+            None => return Ok(None),
+        };
+        let nibbles = |name| field(section, name).map(NibbleArray::from_payload).transpose().map_err(|_| malformed(name));
+        let data = nibbles("Data")?.ok_or_else(|| malformed("Data"))?;
+        let ids = match nibbles("Add")? {
+            Some(add) => blocks.iter().zip(add.iter()).map(|(&b, a)| (a as u16) << 8 | b as u16).collect(),
+            None => blocks.iter().map(|&b| b as u16).collect(),
+        };
+        Ok(Some(Self { ids, data: data.iter().collect() }))
+    }
+
+    /// Reads the blocks of an MCRegion chunk from the `Blocks` and `Data` of
+    /// its `Level`, and splits them into its 8 sections from the bottom up.
+    /// MCRegion orders blocks by x, then z, then y.
+    pub fn from_mcregion(root: &Payload) -> Result<Vec<Self>, Error> {
+        let len = 16 * 16 * MCREGION_HEIGHT;
+        let level = field(root, "Level");
+        let blocks = match level.and_then(|l| field(l, "Blocks")) {
+            Some(Payload::ByteArray(blocks)) if blocks.len() == len => blocks,
+            _ => return Err(malformed("Level.Blocks")),
+        };
+        let data = match level.and_then(|l| field(l, "Data")) {
+            Some(Payload::ByteArray(data)) if data.len() == len / 2 => data,
+            _ => return Err(malformed("Level.Data")),
+        };
+        let mut sections = vec![Self { ids: vec![0; LEN], data: vec![0; LEN] }; MCREGION_HEIGHT / 16];
+        for (i, &id) in blocks.iter().enumerate() {
+            let (x, z, y) = (i >> 11, i >> 7 & 15, i & 127);
+            let section = &mut sections[y >> 4];
+            let j = (y & 15) << 8 | z << 4 | x;
+            section.ids[j] = id as u16;
+            section.data[j] = data[i / 2] >> (i % 2 * 4) & 0x0F;
+        }
+        Ok(sections)
+    }
+
+    /// The id and metadata of the block at `x`, `y`, `z`.
+    pub fn get(&self, x: i32, y: i32, z: i32) -> (u16, u8) {
+        let i = ((y & 15) << 8 | (z & 15) << 4 | (x & 15)) as usize;
+        (self.ids[i], self.data[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::compression::Compression;
+    use crate::error::Reason;
+    use crate::parser::tag::Tag;
+    use crate::region::Region;
+
+    #[test]
+    fn section() {
+        // Orange wool at 1, 2, 3 and block 300 at the top corner
+        let mut blocks = vec![1; LEN];
+        let (wool, top) = (2 << 8 | 3 << 4 | 1, LEN - 1);
+        blocks[wool] = 35;
+        blocks[top] = 300u16 as u8;
+        let mut data = NibbleArray::new();
+        data.set(1, 2, 3, 1);
+        let mut add = NibbleArray::new();
+        add.set(15, 15, 15, 1);
+        let section = Payload::Compound(vec![
+            Tag::new("Y", Payload::Byte(4)),
+            Tag::new("Blocks", Payload::ByteArray(blocks.clone())),
+            Tag::new("Add", add.to_payload()),
+            Tag::new("Data", data.to_payload()),
+        ]);
+        let legacy = LegacyBlocks::from_section(&section).unwrap().unwrap();
+        assert_eq!([legacy.get(1, 2, 3), legacy.get(-1, 15, 15), legacy.get(0, 0, 0)], [(35, 1), (300, 0), (1, 0)]);
+
+        let plain = Payload::Compound(vec![
+            Tag::new("Blocks", Payload::ByteArray(blocks)),
+            Tag::new("Data", data.to_payload()),
+        ]);
+        assert_eq!(LegacyBlocks::from_section(&plain).unwrap().unwrap().get(-1, 15, 15), (44, 0));
+        assert_eq!(LegacyBlocks::from_section(&Payload::Compound(vec![])).unwrap(), None);
+    }
+
+    #[test]
+    fn mcregion() {
+        // Bedrock at the bottom, and torches with metadata 5 and 3 at 4, 70, 9
+        // and the block above
+        let len = 16 * 16 * MCREGION_HEIGHT;
+        let mut blocks = vec![0; len];
+        let mut data = vec![0; len / 2];
+        for column in 0..256 {
+            blocks[column << 7] = 7;
+        }
+        let torch = 4 << 11 | 9 << 7 | 70;
+        blocks[torch] = 50;
+        blocks[torch + 1] = 50;
+        // Even indices take the low half of a byte
+        data[torch / 2] = 3 << 4 | 5;
+        let level = Payload::Compound(vec![
+            Tag::new("xPos", Payload::Int(0)),
+            Tag::new("Blocks", Payload::ByteArray(blocks)),
+            Tag::new("Data", Payload::ByteArray(data)),
+        ]);
+        let root = Tag::new("", Payload::Compound(vec![Tag::new("Level", level)]));
+
+        // MCRegion files share Anvil's layout, so are read the same way
+        let mut region = Region::new(Cursor::new(Vec::new())).unwrap();
+        region.write_chunk(0, 0, &root, Compression::Zlib).unwrap();
+        let root = region.chunk(0, 0).unwrap().unwrap();
+        let sections = LegacyBlocks::from_mcregion(&root.payload).unwrap();
+        assert_eq!(sections.len(), 8);
+        assert_eq!([sections[0].get(3, 0, 3), sections[0].get(3, 1, 3)], [(7, 0), (0, 0)]);
+        assert_eq!([sections[4].get(4, 6, 9), sections[4].get(4, 7, 9), sections[4].get(4, 8, 9)], [(50, 5), (50, 3), (0, 0)]);
+    }
+
+    #[test]
+    fn errors() {
+        let short = Payload::Compound(vec![Tag::new("Blocks", Payload::ByteArray(vec![0; 100]))]);
+        let e = LegacyBlocks::from_section(&short).unwrap_err();
+        assert_eq!((e.reason(), e.path().to_string()), (&Reason::Malformed, "Blocks".into()));
+        let no_data = Payload::Compound(vec![Tag::new("Blocks", Payload::ByteArray(vec![0; LEN]))]);
+        assert_eq!(LegacyBlocks::from_section(&no_data).unwrap_err().path().to_string(), "Data");
+        let e = LegacyBlocks::from_mcregion(&Payload::Compound(vec![])).unwrap_err();
+        assert_eq!(e.path().to_string(), "Level.Blocks");
+    }
+}
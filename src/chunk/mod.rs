@@ -0,0 +1,31 @@
+//! Typed views of what chunk sections pack into arrays, such as the block
+//! states, biomes and light of each 16×16×16 section, and the numeric block
+//! ids of worlds from before 1.13.
+
+mod biomes;
+mod blocks;
+mod legacy;
+mod nibble;
+mod packing;
+
+pub use biomes::{Biomes, LegacyBiomes};
+pub use blocks::BlockStates;
+pub use legacy::LegacyBlocks;
+pub use nibble::NibbleArray;
+pub use packing::{bits, Packing};
+
+use crate::error::{Error, Path, Reason};
+use crate::parser::payload::Payload;
+
+/// The tag called `name` in `compound`, if it is one.
+fn field<'p>(compound: &'p Payload, name: &str) -> Option<&'p Payload> {
+    match compound {
+        Payload::Compound(tags) => tags.iter().find(|t| t.name == name).map(|t| &t.payload),
+        _ => None,
+    }
+}
+
+/// Reports the tag at `path` as not what a section should hold.
+fn malformed(path: &str) -> Error {
+    Error::new(Reason::Malformed, 0, Path::from(path))
+}
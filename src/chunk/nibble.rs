@@ -0,0 +1,108 @@
+//! Arrays of 4 bit values, one per block of a section, such as light levels.
+
+use crate::chunk::malformed;
+use crate::error::Error;
+use crate::parser::payload::Payload;
+
+/// Bytes in the array of a section.
+const LEN: usize = 2048;
+
+/// The 4 bit values of a 16×16×16 section, as `SkyLight`, `BlockLight` and
+/// the pre-1.13 `Data` and `Add` arrays hold them. Blocks are ordered by y,
+/// then z, then x, two to a byte with the first in the low half, and
+/// coordinates are taken modulo 16.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NibbleArray(Vec<u8>);
+
+impl NibbleArray {
+    /// An array of zeros.
+    pub fn new() -> Self {
+        Self(vec![0; LEN])
+    }
+
+    /// Views a `ByteArray` of 2048 bytes.
+    pub fn from_payload(p: &Payload) -> Result<Self, Error> {
+        match p {
+            Payload::ByteArray(bytes) if bytes.len() == LEN => Ok(Self(bytes.clone())),
+            _ => Err(malformed("")),
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32, z: i32) -> u8 {
+        let i = index(x, y, z);
+        self.0[i / 2] >> (i % 2 * 4) & 0x0F
+    }
+
+    /// Sets the value at `x`, `y`, `z` to the low 4 bits of `value`.
+    pub fn set(&mut self, x: i32, y: i32, z: i32, value: u8) {
+        let i = index(x, y, z);
+        let shift = i % 2 * 4;
+        self.0[i / 2] = self.0[i / 2] & !(0x0F << shift) | (value & 0x0F) << shift;
+    }
+
+    /// Every value, in the order above.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.iter().flat_map(|&b| [b & 0x0F, b >> 4])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn to_payload(&self) -> Payload {
+        Payload::ByteArray(self.0.clone())
+    }
+}
+
+impl Default for NibbleArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<NibbleArray> for Payload {
+    fn from(a: NibbleArray) -> Self {
+        Payload::ByteArray(a.0)
+    }
+}
+
+fn index(x: i32, y: i32, z: i32) -> usize {
+    ((y & 15) << 8 | (z & 15) << 4 | (x & 15)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Reason;
+
+    #[test]
+    fn get_set() {
+        let mut light = NibbleArray::new();
+        light.set(0, 0, 0, 15);
+        light.set(1, 0, 0, 7);
+        light.set(2, 1, 3, 0x3C);
+        light.set(-1, 31, 0, 9);
+        assert_eq!(&light.as_bytes()[..2], [0x7F, 0]);
+        assert_eq!(light.as_bytes()[(256 + 3 * 16 + 2) / 2], 0x0C);
+        assert_eq!(light.as_bytes()[(15 * 256 + 15) / 2], 0x90);
+        assert_eq!((light.get(0, 0, 0), light.get(1, 0, 0), light.get(2, 1, 3), light.get(15, 15, 0)), (15, 7, 12, 9));
+        light.set(0, 0, 0, 0);
+        assert_eq!(light.get(1, 0, 0), 7);
+        assert_eq!(light.iter().take(3).collect::<Vec<_>>(), [0, 7, 0]);
+        assert_eq!(light.iter().count(), 4096);
+    }
+
+    #[test]
+    fn payload() {
+        let mut bytes = vec![0; LEN];
+        bytes[100] = 0xA5;
+        let array = NibbleArray::from_payload(&Payload::ByteArray(bytes.clone())).unwrap();
+        assert_eq!((array.get(8, 0, 12), array.get(9, 0, 12)), (5, 10));
+        assert_eq!(array.to_payload(), Payload::ByteArray(bytes.clone()));
+        assert_eq!(Payload::from(array), Payload::ByteArray(bytes));
+
+        for bad in [Payload::ByteArray(vec![0; 2047]), Payload::IntArray(vec![0; 512])] {
+            assert_eq!(NibbleArray::from_payload(&bad).unwrap_err().reason(), &Reason::Malformed);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Packing small unsigned values into the longs of a `LongArray`.
+
+/// How values are laid out in the longs of an array, starting from the
+/// lowest bits of the first long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packing {
+    /// Before 1.16: values follow each other directly, so one can start in
+    /// one long and end in the next.
+    Spanning,
+    /// From 1.16: each long holds as many whole values as fit, leaving its
+    /// highest bits unused.
+    Padded,
+}
+
+impl Packing {
+    /// The packing used by worlds saved with `data_version`, which changed
+    /// in 20w17a.
+    pub fn for_version(data_version: i32) -> Self {
+        match data_version {
+            ..2529 => Self::Spanning,
+            _ => Self::Padded,
+        }
+    }
+
+    /// How many longs `len` values of `bits` bits take.
+    pub fn longs(self, bits: u32, len: usize) -> usize {
+        match (self, bits) {
+            (_, 0) => 0,
+            (Self::Spanning, _) => (len * bits as usize).div_ceil(64),
+            // A value wider than a long takes whole longs of its own
+            (Self::Padded, 65..) => len * bits.div_ceil(64) as usize,
+            (Self::Padded, _) => len.div_ceil((64 / bits) as usize),
+        }
+    }
+
+    /// Unpacks `len` values of `bits` bits, or `None` if `data` is not as
+    /// long as that takes.
+    pub fn unpack(self, data: &[i64], bits: u32, len: usize) -> Option<Vec<u32>> {
+        if bits > 32 || data.len() != self.longs(bits, len) {
+            return None;
+        }
+        if bits == 0 {
+            return Some(vec![0; len]);
+        }
+        let mask = (1u64 << bits) - 1;
+        let values = (0..len).map(|i| {
+            let (long, shift) = self.position(bits, i);
+            let mut value = data[long] as u64 >> shift;
+            if shift + bits as usize > 64 {
+                value |= (data[long + 1] as u64) << (64 - shift);
+            }
+            (value & mask) as u32
+        });
+        Some(values.collect())
+    }
+
+    /// Packs `values` into `bits` bits each. Values must fit.
+    pub fn pack(self, values: &[u32], bits: u32) -> Vec<i64> {
+        if bits == 0 {
+            return Vec::new();
+        }
+        let mut data = vec![0u64; self.longs(bits, values.len())];
+        for (i, &value) in values.iter().enumerate() {
+            debug_assert!(bits >= 32 || value >> bits == 0, "{} does not fit in {} bits", value, bits);
+            let (long, shift) = self.position(bits, i);
+            data[long] |= (value as u64) << shift;
+            if shift > 0 && shift + bits as usize > 64 {
+                data[long + 1] |= value as u64 >> (64 - shift);
+            }
+        }
+        data.into_iter().map(|l| l as i64).collect()
+    }
+
+    /// The long value `i` starts in, and how far into it.
+    fn position(self, bits: u32, i: usize) -> (usize, usize) {
+        match self {
+            Self::Spanning => (i * bits as usize / 64, i * bits as usize % 64),
+            Self::Padded if bits > 64 => (i * bits.div_ceil(64) as usize, 0),
+            Self::Padded => {
+                let per = (64 / bits) as usize;
+                (i / per, i % per * bits as usize)
+            }
+        }
+    }
+}
+
+/// The fewest bits that give each of `n` values its own number, 0 for one.
+pub fn bits(n: usize) -> u32 {
+    usize::BITS - n.saturating_sub(1).leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts() {
+        // With 5 bits, the thirteenth value, 24, spans two longs unless padded
+        let values: Vec<u32> = (12..32).collect();
+        let spanning = Packing::Spanning.pack(&values, 5);
+        assert_eq!(spanning.len(), 2);
+        assert_eq!(spanning[0] as u64 >> 60, 24 & 0xF);
+        assert_eq!(spanning[1] & 0b1, 24 >> 4);
+        let padded = Packing::Padded.pack(&values, 5);
+        assert_eq!(padded.len(), 2);
+        assert_eq!(padded[1] & 0x1F, 24);
+        assert_eq!(padded[0] as u64 >> 60, 0);
+        for (packing, data) in [(Packing::Spanning, &spanning), (Packing::Padded, &padded)] {
+            assert_eq!(packing.unpack(data, 5, 20).unwrap(), values);
+            assert_eq!(packing.unpack(&data[..1], 5, 20), None);
+        }
+        assert_eq!(Packing::Padded.unpack(&[], 0, 3).unwrap(), [0, 0, 0]);
+        assert_eq!(Packing::Spanning.pack(&[u32::MAX, 1], 32), [u32::MAX as i64 | 1 << 32]);
+
+        // Widths past what a u32 holds are refused when unpacking, and
+        // packed whole when packing
+        assert_eq!(Packing::Padded.unpack(&[], 65, 1), None);
+        assert_eq!(Packing::Spanning.unpack(&[0; 2], 65, 1), None);
+        assert_eq!(Packing::Padded.pack(&[1, 2], 65), [1, 0, 2, 0]);
+        assert_eq!(Packing::Spanning.pack(&[1, 2], 65), [1, 4, 0]);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!([1, 2, 3, 16, 17, 4096].map(bits), [0, 1, 2, 4, 5, 12]);
+        assert_eq!(Packing::Spanning.longs(5, 4096), 320);
+        assert_eq!(Packing::Padded.longs(5, 4096), 342);
+        assert_eq!(Packing::Padded.longs(12, 4096), 820);
+        assert_eq!(Packing::Padded.longs(65, 3), 6);
+        assert_eq!([1976, 2529].map(Packing::for_version), [Packing::Spanning, Packing::Padded]);
+    }
+}
@@ -0,0 +1,213 @@
+//! Detection and coding of the compression formats NBT files are stored in.
+//!
+//! Gzip and zlib are coded with `flate2`, which checks the trailing checksums
+//! and reads gzip files of several members as one. LZ4 is the block stream
+//! format chunks use, and needs the `lz4` feature; without it LZ4 data is
+//! still detected but coding it fails.
+
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+#[cfg(feature = "lz4")]
+use crate::lz4;
+
+/// How an NBT file is compressed on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zlib,
+    /// lz4-java's `LZ4BlockOutputStream` format, as used for chunks.
+    Lz4,
+}
+
+impl Compression {
+    /// Guesses the compression of `data` from its first bytes.
+    pub fn detect(data: &[u8]) -> Self {
+        match data {
+            [0x1F, 0x8B, ..] => Self::Gzip,
+            [b'L', b'Z', b'4', b'B', b'l', b'o', b'c', b'k', ..] => Self::Lz4,
+            [cmf, flg, ..] if cmf & 0x0F == 8 && u16::from_be_bytes([*cmf, *flg]) % 31 == 0 => Self::Zlib,
+            _ => Self::None,
+        }
+    }
+
+    /// Decompresses `data`, borrowing it when it is not compressed.
+    pub fn decompress<'a>(&self, data: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+        self.decompress_limited(data, usize::MAX)
+    }
+
+    /// Decompresses `data`, stopping once the output is longer than `limit`.
+    /// Callers can tell the data expands past `limit` from the output length.
+    pub fn decompress_limited<'a>(&self, data: &'a [u8], limit: usize) -> io::Result<Cow<'a, [u8]>> {
+        let mut out = Vec::new();
+        let max = limit.saturating_add(1) as u64;
+        match self {
+            Self::None => return Ok(data.into()),
+            Self::Gzip => { MultiGzDecoder::new(data).take(max).read_to_end(&mut out)?; }
+            Self::Zlib => { ZlibDecoder::new(data).take(max).read_to_end(&mut out)?; }
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => lz4::decompress(data, limit, &mut out)?,
+            #[cfg(not(feature = "lz4"))]
+            Self::Lz4 => return Err(no_lz4()),
+        }
+        Ok(out.into())
+    }
+
+    /// Wraps `r` so that reading it yields the decompressed data, without
+    /// decompressing everything up front.
+    pub fn decoder<'a, R: Read + 'a>(&self, r: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::None => Box::new(r),
+            Self::Gzip => Box::new(MultiGzDecoder::new(r)),
+            Self::Zlib => Box::new(ZlibDecoder::new(r)),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Box::new(lz4::Decoder::new(r)),
+            #[cfg(not(feature = "lz4"))]
+            Self::Lz4 => return Err(no_lz4()),
+        })
+    }
+
+    /// Compresses `data`, borrowing it when no compression is requested.
+    pub fn compress<'a>(&self, data: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+        let level = flate2::Compression::default();
+        match self {
+            Self::None => Ok(data.into()),
+            Self::Gzip => {
+                let mut e = GzEncoder::new(Vec::new(), level);
+                e.write_all(data)?;
+                Ok(e.finish()?.into())
+            }
+            Self::Zlib => {
+                let mut e = ZlibEncoder::new(Vec::new(), level);
+                e.write_all(data)?;
+                Ok(e.finish()?.into())
+            }
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Ok(lz4::compress(data).into()),
+            #[cfg(not(feature = "lz4"))]
+            Self::Lz4 => Err(no_lz4()),
+        }
+    }
+}
+
+#[cfg(not(feature = "lz4"))]
+fn no_lz4() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "LZ4 needs the lz4 feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::Compression;
+
+    // "hello" compressed with `gzip -n` and python's zlib.compress
+    const GZIP: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
+        0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00,
+        0x86, 0xa6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00,
+        ];
+    const ZLIB: &[u8] = &[
+        0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x06, 0x2c, 0x02, 0x15,
+        ];
+
+    #[test]
+    fn detect() {
+        assert_eq!(Compression::detect(GZIP), Compression::Gzip);
+        assert_eq!(Compression::detect(ZLIB), Compression::Zlib);
+        assert_eq!(Compression::detect(&[0x0A, 0x00, 0x00]), Compression::None);
+        assert_eq!(Compression::detect(&[]), Compression::None);
+    }
+
+    #[test]
+    fn decompress() {
+        assert_eq!(&*Compression::Gzip.decompress(GZIP).unwrap(), b"hello");
+        assert_eq!(&*Compression::Zlib.decompress(ZLIB).unwrap(), b"hello");
+        assert_eq!(&*Compression::None.decompress(b"hello").unwrap(), b"hello");
+        assert_eq!(&*Compression::Gzip.decompress_limited(GZIP, 2).unwrap(), b"hel");
+        assert_eq!(&*Compression::Zlib.decompress_limited(ZLIB, 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"hello hello hello hello";
+        for c in [Compression::None, Compression::Gzip, Compression::Zlib, #[cfg(feature = "lz4")] Compression::Lz4] {
+            let compressed = c.compress(data).unwrap();
+            assert_eq!(Compression::detect(&compressed), c);
+            assert_eq!(&*c.decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn decoder() {
+        for (data, c) in [(GZIP, Compression::Gzip), (ZLIB, Compression::Zlib), (&b"hello"[..], Compression::None)] {
+            let mut out = Vec::new();
+            c.decoder(data).unwrap().read_to_end(&mut out).unwrap();
+            assert_eq!(out, b"hello");
+        }
+        // Checksums are checked when the stream ends
+        let mut bad = GZIP.to_vec();
+        bad[18] ^= 1;
+        for data in [&GZIP[..5], &bad] {
+            assert!(Compression::Gzip.decoder(data).unwrap().read_to_end(&mut Vec::new()).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn lz4_decoder() {
+        // LZ4 is decoded a block at a time, so a bad block is only found
+        // when it is reached
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i / 300) as u8).collect();
+        let mut packed = Compression::Lz4.compress(&data).unwrap().into_owned();
+        let mut out = Vec::new();
+        Compression::Lz4.decoder(&packed[..]).unwrap().read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+        let last = packed.len() - 30;
+        packed[last] ^= 1;
+        let mut out = vec![0; 1000];
+        let mut r = Compression::Lz4.decoder(&packed[..]).unwrap();
+        r.read_exact(&mut out).unwrap();
+        assert_eq!(out, data[..1000]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "lz4"))]
+    fn no_lz4() {
+        let e = Compression::Lz4.compress(b"hello").unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::Unsupported);
+        assert!(Compression::Lz4.decoder(&b"LZ4Block"[..]).is_err());
+        assert_eq!(Compression::detect(b"LZ4Block"), Compression::Lz4);
+    }
+
+    #[test]
+    fn corrupt() {
+        assert!(Compression::Gzip.decompress(&GZIP[..12]).is_err());
+        let mut bad = GZIP.to_vec();
+        bad[21] = 6;
+        assert!(Compression::Gzip.decompress(&bad).is_err());
+        let mut bad = GZIP.to_vec();
+        bad[17] ^= 1;
+        assert!(Compression::Gzip.decompress(&bad).is_err());
+        // A header with an FEXTRA field longer than the data
+        let mut extra = GZIP[..10].to_vec();
+        extra[3] = 1 << 2;
+        extra.extend([0xFF, 0x00, 0x01]);
+        assert!(Compression::Gzip.decompress(&extra).is_err());
+    }
+
+    #[test]
+    fn members() {
+        let two = [GZIP, GZIP].concat();
+        assert_eq!(&*Compression::Gzip.decompress(&two).unwrap(), b"hellohello");
+        let mut out = Vec::new();
+        Compression::Gzip.decoder(&two[..]).unwrap().read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"hellohello");
+    }
+}
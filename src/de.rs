@@ -0,0 +1,734 @@
+//! Deserializing Rust types from NBT with serde.
+//!
+//! Compounds map to structs and maps, lists and arrays to sequences, and a
+//! byte to a `bool` when one is asked for. Enums are read from a string
+//! naming a unit variant, or a compound with a single tag naming the variant.
+//! Use the wrappers in `array` to insist on an array type. Unsigned
+//! integers are read from the bits of the signed type of the same width.
+//!
+//! `from_bytes` and `from_reader` deserialize straight from the wire
+//! format, skipping tags the type does not ask for, without building a
+//! tree first.
+
+use std::io::Read;
+use std::vec;
+
+use serde::de::value::SeqDeserializer;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::array::{BYTE_ARRAY, INT_ARRAY, LONG_ARRAY};
+use crate::error::{Error, Reason, Segment};
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+use crate::parser::Options;
+use crate::stream::Reader;
+
+/// Deserializes the root tag at the start of uncompressed `data`.
+pub fn from_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    from_bytes_with(data, Options::default())
+}
+
+pub fn from_bytes_with<T: DeserializeOwned>(data: &[u8], opts: Options) -> Result<T, Error> {
+    from_reader(data, opts)
+}
+
+/// Deserializes the root tag read from `reader`, which may be a
+/// decompressing stream.
+pub fn from_reader<T: DeserializeOwned, R: Read>(reader: R, opts: Options) -> Result<T, Error> {
+    let mut reader = Reader::new(reader, opts);
+    let id = reader.root_id()?;
+    T::deserialize(Stream { reader: &mut reader, id })
+}
+
+pub fn from_payload<T: DeserializeOwned>(payload: Payload) -> Result<T, Error> {
+    T::deserialize(payload)
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Payload {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Payload {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::End => v.visit_unit(),
+            Payload::Byte(x) => v.visit_i8(x),
+            Payload::Short(x) => v.visit_i16(x),
+            Payload::Int(x) => v.visit_i32(x),
+            Payload::Long(x) => v.visit_i64(x),
+            Payload::Float(x) => v.visit_f32(x),
+            Payload::Double(x) => v.visit_f64(x),
+            Payload::ByteArray(x) => visit_array(v, x.into_iter().map(|b| Payload::Byte(b as i8))),
+            Payload::String(x) => v.visit_string(x),
+            Payload::List(_, x) => {
+                let len = x.len();
+                let mut list = List { items: x.into_iter().enumerate() };
+                let value = v.visit_seq(&mut list)?;
+                match list.items.len() {
+                    0 => Ok(value),
+                    _ => Err(de::Error::invalid_length(len, &"fewer elements in list")),
+                }
+            }
+            Payload::Compound(x) => v.visit_map(Compound { tags: x.into_iter(), value: None }),
+            Payload::IntArray(x) => visit_array(v, x.into_iter().map(Payload::Int)),
+            Payload::LongArray(x) => visit_array(v, x.into_iter().map(Payload::Long)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::Byte(x) => v.visit_bool(x != 0),
+            other => other.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::Byte(x) => v.visit_u8(x as u8),
+            other => other.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::Short(x) => v.visit_u16(x as u16),
+            other => other.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::Int(x) => v.visit_u32(x as u32),
+            other => other.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::Long(x) => v.visit_u64(x as u64),
+            other => other.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::End => v.visit_none(),
+            other => v.visit_some(other),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(v)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self {
+            Payload::ByteArray(x) => v.visit_byte_buf(x),
+            other => other.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, v: V) -> Result<V::Value, Error> {
+        match (name, self) {
+            (BYTE_ARRAY, Payload::ByteArray(x)) => v.visit_byte_buf(x),
+            (INT_ARRAY, Payload::IntArray(x)) => visit_array(v, x.into_iter().map(Payload::Int)),
+            (LONG_ARRAY, Payload::LongArray(x)) => visit_array(v, x.into_iter().map(Payload::Long)),
+            (BYTE_ARRAY | INT_ARRAY | LONG_ARRAY, other) => Err(de::Error::invalid_type(unexpected(&other), &v)),
+            (_, other) => v.visit_newtype_struct(other),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Payload::String(x) => v.visit_enum(x.into_deserializer()),
+            Payload::Compound(mut x) if x.len() == 1 => v.visit_enum(Variant(x.remove(0))),
+            other => Err(de::Error::invalid_type(unexpected(&other), &v)),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        v.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+fn visit_array<'de, V, I>(v: V, items: I) -> Result<V::Value, Error>
+where
+    V: Visitor<'de>,
+    I: Iterator,
+    I::Item: IntoDeserializer<'de, Error>,
+{
+    let mut seq = SeqDeserializer::new(items);
+    let value = v.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+fn unexpected(p: &Payload) -> Unexpected<'_> {
+    match p {
+        Payload::End => Unexpected::Unit,
+        Payload::Byte(x) => Unexpected::Signed(*x as i64),
+        Payload::Short(x) => Unexpected::Signed(*x as i64),
+        Payload::Int(x) => Unexpected::Signed(*x as i64),
+        Payload::Long(x) => Unexpected::Signed(*x),
+        Payload::Float(x) => Unexpected::Float(*x as f64),
+        Payload::Double(x) => Unexpected::Float(*x),
+        Payload::ByteArray(_) => Unexpected::Other("byte array"),
+        Payload::String(x) => Unexpected::Str(x),
+        Payload::List(..) => Unexpected::Seq,
+        Payload::Compound(_) => Unexpected::Map,
+        Payload::IntArray(_) => Unexpected::Other("int array"),
+        Payload::LongArray(_) => Unexpected::Other("long array"),
+    }
+}
+
+struct List {
+    items: std::iter::Enumerate<vec::IntoIter<Payload>>,
+}
+
+impl<'de> de::SeqAccess<'de> for List {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        match self.items.next() {
+            Some((i, p)) => seed.deserialize(p).map(Some).map_err(|e| e.within(Segment::Index(i))),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct Compound {
+    tags: vec::IntoIter<Tag>,
+    /// The tag whose key was just read.
+    value: Option<Tag>,
+}
+
+impl<'de> de::MapAccess<'de> for Compound {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        let Some(tag) = self.tags.next() else { return Ok(None) };
+        let key = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(tag.name.as_str()))?;
+        self.value = Some(tag);
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let tag = self.value.take().ok_or_else(|| <Error as de::Error>::custom("value requested before key"))?;
+        seed.deserialize(tag.payload).map_err(|e| e.within(Segment::Name(tag.name)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.tags.len())
+    }
+}
+
+/// An enum variant stored as a compound with a single tag.
+struct Variant(Tag);
+
+impl<'de> de::EnumAccess<'de> for Variant {
+    type Error = Error;
+    type Variant = Variant;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Variant), Error> {
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.0.name.as_str()))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let Tag { name, payload } = self.0;
+        seed.deserialize(payload).map_err(|e| e.within(Segment::Name(name)))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, v: V) -> Result<V::Value, Error> {
+        let Tag { name, payload } = self.0;
+        de::Deserializer::deserialize_seq(payload, v).map_err(|e| e.within(Segment::Name(name)))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], v: V) -> Result<V::Value, Error> {
+        let Tag { name, payload } = self.0;
+        de::Deserializer::deserialize_map(payload, v).map_err(|e| e.within(Segment::Name(name)))
+    }
+}
+
+/// The payload with id `id` next in `reader`, deserialized as it is read.
+/// Paths are added to errors on the way out, as for `Payload`, so the
+/// reader's own path stays empty.
+struct Stream<'r, R> {
+    reader: &'r mut Reader<R>,
+    id: i8,
+}
+
+impl<R: Read> Stream<'_, R> {
+    fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let len = self.reader.length(1)?;
+        self.reader.bytes(len)
+    }
+
+    /// Visits `len` payloads with id `id` as a sequence.
+    fn elements<'de, V: Visitor<'de>>(&mut self, v: V, id: i8, len: usize) -> Result<V::Value, Error> {
+        let mut seq = Elements { reader: self.reader, id, len, next: 0 };
+        let value = v.visit_seq(&mut seq)?;
+        match seq.next == len {
+            true => Ok(value),
+            false => Err(de::Error::invalid_length(len, &"fewer elements")),
+        }
+    }
+
+    /// Reads the payload to report that it is not of the type `v` expects.
+    fn invalid<'de, V: Visitor<'de>, T>(self, v: V) -> Result<T, Error> {
+        let p = match self.id {
+            0 => Payload::End,
+            id => self.reader.payload(id)?,
+        };
+        Err(de::Error::invalid_type(unexpected(&p), &v))
+    }
+}
+
+impl<'de, R: Read> de::Deserializer<'de> for Stream<'_, R> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(mut self, v: V) -> Result<V::Value, Error> {
+        let enc = self.reader.options().encoding;
+        match self.id {
+            0 => v.visit_unit(),
+            1 => v.visit_i8(self.reader.byte()?),
+            2 => v.visit_i16(self.reader.short()?),
+            3 => v.visit_i32(self.reader.int()?),
+            4 => v.visit_i64(self.reader.long()?),
+            5 => v.visit_f32(self.reader.float()?),
+            6 => v.visit_f64(self.reader.double()?),
+            7 => {
+                let len = self.reader.length(1)?;
+                self.elements(v, 1, len)
+            }
+            8 => {
+                let len = self.reader.string_length()?;
+                v.visit_string(self.reader.string(len)?)
+            }
+            9 => {
+                let (id, len) = self.reader.list_header()?;
+                let value = self.elements(v, id, len)?;
+                self.reader.leave();
+                Ok(value)
+            }
+            10 => {
+                self.reader.enter(self.reader.offset())?;
+                let mut map = Tags { reader: self.reader, value: None, done: false };
+                let value = v.visit_map(&mut map)?;
+                map.finish()?;
+                Ok(value)
+            }
+            11 => {
+                let len = self.reader.length(enc.width(4))?;
+                self.elements(v, 3, len)
+            }
+            12 => {
+                let len = self.reader.length(enc.width(8))?;
+                self.elements(v, 4, len)
+            }
+            id => Err(self.reader.fail(Reason::UnknownTag(id), self.reader.offset())),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self.id {
+            1 => v.visit_bool(self.reader.byte()? != 0),
+            _ => self.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self.id {
+            1 => v.visit_u8(self.reader.byte()? as u8),
+            _ => self.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self.id {
+            2 => v.visit_u16(self.reader.short()? as u16),
+            _ => self.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self.id {
+            3 => v.visit_u32(self.reader.int()? as u32),
+            _ => self.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self.id {
+            4 => v.visit_u64(self.reader.long()? as u64),
+            _ => self.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        match self.id {
+            0 => v.visit_none(),
+            _ => v.visit_some(self),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(v)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(mut self, v: V) -> Result<V::Value, Error> {
+        match self.id {
+            7 => v.visit_byte_buf(self.bytes()?),
+            _ => self.deserialize_any(v),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(mut self, name: &'static str, v: V) -> Result<V::Value, Error> {
+        match (name, self.id) {
+            (BYTE_ARRAY, 7) => v.visit_byte_buf(self.bytes()?),
+            (INT_ARRAY, 11) | (LONG_ARRAY, 12) => self.deserialize_any(v),
+            (BYTE_ARRAY | INT_ARRAY | LONG_ARRAY, _) => self.invalid(v),
+            _ => v.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value, Error> {
+        match self.id {
+            8 => {
+                let len = self.reader.string_length()?;
+                v.visit_enum(self.reader.string(len)?.into_deserializer())
+            }
+            10 => {
+                self.reader.enter(self.reader.offset())?;
+                let at = self.reader.offset();
+                let (id, name) = match self.reader.byte()? {
+                    0 => return Err(de::Error::invalid_type(Unexpected::Map, &v)),
+                    id => self.reader.named(id, at)?,
+                };
+                let value = v.visit_enum(Tagged { reader: &mut *self.reader, id, name })?;
+                if self.reader.byte()? != 0 {
+                    return Err(de::Error::invalid_value(Unexpected::Map, &"a compound with a single tag"));
+                }
+                self.reader.leave();
+                Ok(value)
+            }
+            _ => self.invalid(v),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, v: V) -> Result<V::Value, Error> {
+        if self.id != 0 {
+            self.reader.skip(self.id)?;
+        }
+        v.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+/// The elements of an array or list, read one at a time.
+struct Elements<'r, R> {
+    reader: &'r mut Reader<R>,
+    id: i8,
+    len: usize,
+    next: usize,
+}
+
+impl<'de, R: Read> de::SeqAccess<'de> for Elements<'_, R> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        if self.next == self.len {
+            return Ok(None);
+        }
+        let i = self.next;
+        self.next += 1;
+        let stream = Stream { reader: &mut *self.reader, id: self.id };
+        seed.deserialize(stream).map(Some).map_err(|e| e.within(Segment::Index(i)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.next)
+    }
+}
+
+/// The tags of a compound that has been entered, read one at a time.
+struct Tags<'r, R> {
+    reader: &'r mut Reader<R>,
+    /// The id and name of the tag whose key was just read.
+    value: Option<(i8, String)>,
+    /// The end tag has been read.
+    done: bool,
+}
+
+impl<R: Read> Tags<'_, R> {
+    /// Skips whatever the visitor left unread, and leaves the compound.
+    fn finish(mut self) -> Result<(), Error> {
+        if let Some((id, _)) = self.value.take() {
+            self.reader.skip(id)?;
+        }
+        if !self.done {
+            return self.reader.skip_compound();
+        }
+        self.reader.leave();
+        Ok(())
+    }
+}
+
+impl<'de, R: Read> de::MapAccess<'de> for Tags<'_, R> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        if let Some((id, _)) = self.value.take() {
+            self.reader.skip(id)?;
+        }
+        if self.done {
+            return Ok(None);
+        }
+        let at = self.reader.offset();
+        let (id, name) = match self.reader.byte()? {
+            0 => {
+                self.done = true;
+                return Ok(None);
+            }
+            id => self.reader.named(id, at)?,
+        };
+        let key = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(name.as_str()))?;
+        self.value = Some((id, name));
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (id, name) = self.value.take().ok_or_else(|| <Error as de::Error>::custom("value requested before key"))?;
+        let stream = Stream { reader: &mut *self.reader, id };
+        seed.deserialize(stream).map_err(|e| e.within(Segment::Name(name)))
+    }
+}
+
+/// An enum variant read from a compound with a single tag, the compound
+/// having been entered and the tag's header read.
+struct Tagged<'r, R> {
+    reader: &'r mut Reader<R>,
+    id: i8,
+    name: String,
+}
+
+impl<'de, 'r, R: Read> de::EnumAccess<'de> for Tagged<'r, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.name.as_str()))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, R: Read> de::VariantAccess<'de> for Tagged<'_, R> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        self.reader.skip(self.id)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let stream = Stream { reader: self.reader, id: self.id };
+        seed.deserialize(stream).map_err(|e| e.within(Segment::Name(self.name)))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, v: V) -> Result<V::Value, Error> {
+        let stream = Stream { reader: self.reader, id: self.id };
+        de::Deserializer::deserialize_seq(stream, v).map_err(|e| e.within(Segment::Name(self.name)))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], v: V) -> Result<V::Value, Error> {
+        let stream = Stream { reader: self.reader, id: self.id };
+        de::Deserializer::deserialize_map(stream, v).map_err(|e| e.within(Segment::Name(self.name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::array::{ByteArray, IntArray, LongArray};
+    use crate::writer;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Level {
+        level_name: String,
+        hardcore: bool,
+        spawn_x: i32,
+        time: i64,
+        seed: Option<i64>,
+        game_rules: HashMap<String, String>,
+        player: Player,
+        difficulty: Difficulty,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Player {
+        #[serde(rename = "Pos")]
+        pos: Vec<f64>,
+        #[serde(rename = "UUID")]
+        uuid: IntArray,
+        inventory: Vec<Item>,
+        blocks: ByteArray,
+        states: LongArray,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        id: String,
+        count: i8,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Difficulty {
+        Easy,
+        Custom(u8),
+    }
+
+    fn level() -> Payload {
+        Payload::Compound(vec![
+            Tag::new("LevelName", Payload::String("world".into())),
+            Tag::new("Hardcore", Payload::Byte(1)),
+            Tag::new("SpawnX", Payload::Int(-20)),
+            Tag::new("Time", Payload::Long(1 << 40)),
+            Tag::new("GameRules", Payload::Compound(vec![Tag::new("keepInventory", Payload::String("true".into()))])),
+            Tag::new("Player", Payload::Compound(vec![
+                Tag::new("Pos", Payload::list(vec![Payload::Double(0.5), Payload::Double(64.0), Payload::Double(-3.5)])),
+                Tag::new("UUID", Payload::IntArray(vec![1, 2, 3, 4])),
+                Tag::new("inventory", Payload::list(vec![Payload::Compound(vec![
+                    Tag::new("id", Payload::String("minecraft:stone".into())),
+                    Tag::new("count", Payload::Byte(64)),
+                ])])),
+                Tag::new("blocks", Payload::ByteArray(vec![0, 255])),
+                Tag::new("states", Payload::LongArray(vec![-1])),
+            ])),
+            Tag::new("Difficulty", Payload::Compound(vec![Tag::new("Custom", Payload::Byte(7))])),
+            Tag::new("Ignored", Payload::LongArray(vec![1, 2])),
+        ])
+    }
+
+    #[test]
+    fn structs() {
+        let level: Level = from_payload(level()).unwrap();
+        assert_eq!(level.level_name, "world");
+        assert!(level.hardcore);
+        assert_eq!((level.spawn_x, level.time, level.seed), (-20, 1 << 40, None));
+        assert_eq!(level.game_rules["keepInventory"], "true");
+        assert_eq!(level.player.pos, [0.5, 64.0, -3.5]);
+        assert_eq!(level.player.uuid, IntArray(vec![1, 2, 3, 4]));
+        assert_eq!(level.player.inventory, [Item { id: "minecraft:stone".into(), count: 64 }]);
+        assert_eq!(level.player.blocks, ByteArray(vec![0, 255]));
+        assert_eq!(level.player.states, LongArray(vec![-1]));
+        assert_eq!(level.difficulty, Difficulty::Custom(7));
+
+        assert_eq!(from_payload::<Difficulty>(Payload::String("Easy".into())).unwrap(), Difficulty::Easy);
+        assert_eq!(from_payload::<Vec<i32>>(Payload::IntArray(vec![1, 2])).unwrap(), [1, 2]);
+        assert_eq!(from_payload::<(i8, i8)>(Payload::ByteArray(vec![1, 2])).unwrap(), (1, 2));
+
+        // Unsigned numbers take the bits of the signed type of their width
+        assert_eq!(from_payload::<Vec<u8>>(Payload::ByteArray(vec![200])).unwrap(), [200]);
+        assert_eq!(from_payload::<u8>(Payload::Byte(-56)).unwrap(), 200);
+        assert_eq!(from_payload::<Vec<u32>>(Payload::IntArray(vec![-1])).unwrap(), [u32::MAX]);
+        assert_eq!(from_payload::<u64>(Payload::Long(-1)).unwrap(), u64::MAX);
+        let mut data = Vec::new();
+        writer::tag(&mut data, &Tag::new("", Payload::Compound(vec![Tag::new("b", Payload::ByteArray(vec![200]))])))
+            .unwrap();
+        let bytes: HashMap<String, Vec<u8>> = super::from_bytes(&data).unwrap();
+        assert_eq!(bytes["b"], [200]);
+    }
+
+    #[test]
+    fn from_bytes() {
+        let mut data = Vec::new();
+        writer::tag(&mut data, &Tag::new("", level())).unwrap();
+        let level: Level = super::from_bytes(&data).unwrap();
+        assert_eq!(level, from_payload(self::level()).unwrap());
+        let streamed: Level = from_reader(&data[..], Options::default()).unwrap();
+        assert_eq!(streamed, level);
+
+        // Tags the type skips are still checked as they are read past
+        let e = super::from_bytes::<Level>(&data[..data.len() - 4]).unwrap_err();
+        assert_eq!((e.reason(), e.path().to_string()), (&Reason::Truncated, "Ignored".into()));
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Name {
+            #[serde(rename = "LevelName")]
+            name: String,
+        }
+        let name: Name = super::from_bytes(&data).unwrap();
+        assert_eq!(name.name, "world");
+    }
+
+    #[test]
+    fn errors() {
+        let mut p = level();
+        let Payload::Compound(tags) = &mut p else { unreachable!() };
+        let Payload::Compound(player) = &mut tags[5].payload else { unreachable!() };
+        player[1].payload = Payload::list(vec![Payload::Int(1)]);
+        let mut data = Vec::new();
+        writer::tag(&mut data, &Tag::new("", p.clone())).unwrap();
+        let e = from_payload::<Level>(p).unwrap_err();
+        assert_eq!(e.path().to_string(), "Player.UUID");
+        assert_eq!(e.to_string(), "invalid type: sequence, expected an int array in Player.UUID");
+        assert_eq!(super::from_bytes::<Level>(&data).unwrap_err().to_string(), e.to_string());
+
+        let e = from_payload::<Item>(Payload::Compound(vec![Tag::new("id", Payload::Int(1))])).unwrap_err();
+        assert!(matches!(e.reason(), Reason::Custom(_)));
+        assert_eq!(e.path().to_string(), "id");
+        let e = from_payload::<Item>(Payload::Compound(vec![])).unwrap_err();
+        assert_eq!(e.to_string(), "missing field `id`");
+        assert!(from_payload::<(i8, i8)>(Payload::ByteArray(vec![1, 2, 3])).is_err());
+        assert!(from_payload::<(i8, i8)>(Payload::list(vec![Payload::Byte(1); 3])).is_err());
+
+        let mut data = Vec::new();
+        writer::tag(&mut data, &Tag::new("", Payload::Compound(vec![Tag::new("id", Payload::list(vec![Payload::Int(1)]))])))
+            .unwrap();
+        let e = super::from_bytes::<Item>(&data).unwrap_err();
+        assert_eq!(e.path().to_string(), "id");
+        let e = super::from_bytes::<Vec<Item>>(&[0x09, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x00]).unwrap_err();
+        assert_eq!((e.path().to_string(), e.to_string()), ("[0]".into(), "missing field `id` in [0]".into()));
+    }
+}
@@ -0,0 +1,309 @@
+//! Errors reported while reading NBT.
+
+use std::fmt;
+use std::io;
+
+/// Why, where and in which tag reading failed.
+#[derive(Debug)]
+pub struct Error {
+    reason: Reason,
+    offset: usize,
+    path: Path,
+    source: Option<io::Error>,
+}
+
+impl Error {
+    pub fn new(reason: Reason, offset: usize, path: Path) -> Self {
+        Self { reason, offset, path, source: None }
+    }
+
+    /// An I/O error hit `offset` bytes into the data.
+    pub fn io(e: io::Error, offset: usize, path: Path) -> Self {
+        Self { reason: Reason::Io, offset, path, source: Some(e) }
+    }
+
+    pub fn reason(&self) -> &Reason {
+        &self.reason
+    }
+
+    /// Position of the failure in the uncompressed data.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Tag that was being read, relative to the root.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records that the error happened inside `segment`.
+    #[cfg(feature = "serde")]
+    pub(crate) fn within(mut self, segment: Segment) -> Self {
+        self.path.0.insert(0, segment);
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(e) => write!(f, "{}", e)?,
+            None => write!(f, "{}", self.reason)?,
+        }
+        // Serde errors concern values rather than bytes, so have no offset
+        if !matches!(self.reason, Reason::Custom(_)) {
+            write!(f, " at byte {}", self.offset)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " in {}", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_ref().map(|e| e as _)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::new(Reason::Custom(msg.to_string()), 0, Path::default())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::new(Reason::Custom(msg.to_string()), 0, Path::default())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::io(e, 0, Path::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// Reading the underlying data failed; see `Error::source`.
+    Io,
+    /// A tag id outside the known range.
+    UnknownTag(i8),
+    /// The data ends in the middle of a value.
+    Truncated,
+    /// A string is not valid in the string encoding in use.
+    InvalidString,
+    /// An array or list has a negative length.
+    NegativeLength(i32),
+    /// A VarInt runs past its maximum size.
+    VarIntTooLong,
+    /// A file's root tag has this id instead of being a compound.
+    RootNotCompound(i8),
+    /// Compounds and lists are nested deeper than `Limits::depth`.
+    DepthLimit,
+    /// More than `Limits::bytes` would be read.
+    SizeLimit,
+    /// An array or list has more than `Limits::length` elements.
+    LengthLimit(usize),
+    /// A region file chunk has this compression type.
+    UnknownCompression(u8),
+    /// Any other malformed input.
+    Malformed,
+    /// SNBT text that does not parse; says what was expected instead.
+    Syntax(String),
+    /// A message from serde, such as a missing field or a mixed list.
+    Custom(String),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Io => write!(f, "i/o error"),
+            Reason::UnknownTag(id) => write!(f, "unknown tag id {}", id),
+            Reason::Truncated => write!(f, "unexpected end of data"),
+            Reason::InvalidString => write!(f, "invalid string"),
+            Reason::NegativeLength(len) => write!(f, "negative length {}", len),
+            Reason::VarIntTooLong => write!(f, "varint too long"),
+            Reason::RootNotCompound(id) => write!(f, "root tag has id {}, expected a compound", id),
+            Reason::DepthLimit => write!(f, "nesting depth limit exceeded"),
+            Reason::SizeLimit => write!(f, "size limit exceeded"),
+            Reason::LengthLimit(len) => write!(f, "length {} exceeds the limit", len),
+            Reason::UnknownCompression(kind) => write!(f, "unknown compression type {}", kind),
+            Reason::Malformed => write!(f, "malformed data"),
+            Reason::Syntax(msg) | Reason::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// Location of a tag in a tree, such as `Level.Sections[3].BlockStates`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Child of a compound.
+    Name(String),
+    /// Element of a list.
+    Index(usize),
+}
+
+impl Path {
+    pub fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn push(&mut self, segment: Segment) {
+        self.0.push(segment)
+    }
+
+    pub fn pop(&mut self) -> Option<Segment> {
+        self.0.pop()
+    }
+}
+
+impl From<Vec<Segment>> for Path {
+    fn from(segments: Vec<Segment>) -> Self {
+        Self(segments)
+    }
+}
+
+/// Splits on `.`, with a trailing `[n]` on a segment read as a list index,
+/// the inverse of `Display`.
+impl From<&str> for Path {
+    fn from(s: &str) -> Self {
+        let mut path = Path::default();
+        for part in s.split('.') {
+            let (name, indices) = split_indices(part);
+            if !name.is_empty() {
+                path.push(Segment::Name(name.into()));
+            }
+            path.0.extend(indices.into_iter().map(Segment::Index));
+        }
+        path
+    }
+}
+
+/// Splits the trailing `[n]` indices off a path segment.
+fn split_indices(mut part: &str) -> (&str, Vec<usize>) {
+    let mut indices = Vec::new();
+    while let Some((name, index)) = part.strip_suffix(']').and_then(|p| p.rsplit_once('[')) {
+        match index.parse() {
+            Ok(i) => indices.push(i),
+            Err(_) => break,
+        }
+        part = name;
+    }
+    indices.reverse();
+    (part, indices)
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Name(name) if i == 0 => write!(f, "{}", name)?,
+                Segment::Name(name) => write!(f, ".{}", name)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error type of the nom parsers in `parser`.
+///
+/// Holds the input remaining where parsing failed, and collects the path on
+/// the way back out of nested compounds and lists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError<'a> {
+    pub input: &'a [u8],
+    pub reason: Reason,
+    /// Innermost segment first.
+    path: Vec<Segment>,
+}
+
+impl<'a> ParseError<'a> {
+    pub fn new(input: &'a [u8], reason: Reason) -> Self {
+        Self { input, reason, path: Vec::new() }
+    }
+
+    /// Records that the failure happened inside `segment`.
+    pub fn within(mut self, segment: Segment) -> Self {
+        self.path.push(segment);
+        self
+    }
+
+    /// Converts into an `Error`, `origin` being the whole input parsing
+    /// started from.
+    pub fn into_error(mut self, origin: &[u8]) -> Error {
+        self.path.reverse();
+        Error::new(self.reason, origin.len() - self.input.len(), Path(self.path))
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
+    fn from_error_kind(input: &'a [u8], kind: nom::error::ErrorKind) -> Self {
+        let reason = match kind {
+            nom::error::ErrorKind::Eof => Reason::Truncated,
+            _ => Reason::Malformed,
+        };
+        Self::new(input, reason)
+    }
+
+    fn append(_: &'a [u8], _: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+/// Fails a nom parser at `input`.
+pub fn fail<'a, T>(input: &'a [u8], reason: Reason) -> nom::IResult<&'a [u8], T, ParseError<'a>> {
+    Err(nom::Err::Error(ParseError::new(input, reason)))
+}
+
+/// Converts the result of a nom parser that started at `origin`.
+pub fn finish<'a, T>(origin: &'a [u8], result: nom::IResult<&'a [u8], T, ParseError<'a>>) -> Result<(T, &'a [u8]), Error> {
+    match result {
+        Ok((rest, value)) => Ok((value, rest)),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e.into_error(origin)),
+        Err(nom::Err::Incomplete(_)) => Err(Error::new(Reason::Truncated, origin.len(), Path::default())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path() {
+        let path = Path(vec![
+            Segment::Name("Level".into()),
+            Segment::Name("Sections".into()),
+            Segment::Index(3),
+            Segment::Name("BlockStates".into()),
+        ]);
+        assert_eq!(path.to_string(), "Level.Sections[3].BlockStates");
+        assert_eq!(Path(vec![Segment::Index(0), Segment::Index(1)]).to_string(), "[0][1]");
+        assert_eq!(Path::from("Level.Sections[3].BlockStates"), path);
+        assert_eq!(Path::from("a[0][1]").to_string(), "a[0][1]");
+        assert_eq!(Path::from("a[x]").segments(), [Segment::Name("a[x]".into())]);
+    }
+
+    #[test]
+    fn into_error() {
+        let origin = [0u8; 10];
+        let e = ParseError::new(&origin[7..], Reason::Truncated)
+            .within(Segment::Index(2))
+            .within(Segment::Name("a".into()))
+            .into_error(&origin);
+        assert_eq!((e.reason(), e.offset()), (&Reason::Truncated, 7));
+        assert_eq!(e.to_string(), "unexpected end of data at byte 7 in a[2]");
+    }
+}
@@ -0,0 +1,253 @@
+//! Pull parsing: walk NBT as a sequence of events instead of building a tree.
+
+use std::io::Read;
+
+use crate::error::{Error, Reason, Segment};
+use crate::parser::payload::Payload;
+use crate::parser::Options;
+use crate::stream::Reader;
+
+/// One step through a tag tree. Names are `None` for list elements and a
+/// nameless root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    StartCompound(Option<String>),
+    EndCompound,
+    /// Name, element id and length.
+    StartList(Option<String>, i8, usize),
+    EndList,
+    /// Any payload other than a compound or list.
+    Value(Option<String>, Payload),
+}
+
+enum Frame {
+    Compound,
+    List { id: i8, len: usize, next: usize },
+}
+
+/// Yields the events of one root tag read from `R`.
+pub struct Events<R> {
+    reader: Reader<R>,
+    stack: Vec<Frame>,
+    started: bool,
+}
+
+impl<R: Read> Events<R> {
+    pub fn new(inner: R, opts: Options) -> Self {
+        Self { reader: Reader::new(inner, opts), stack: Vec::new(), started: false }
+    }
+
+    /// Bytes read so far.
+    pub fn offset(&self) -> usize {
+        self.reader.offset()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// The next event, or `None` once the root tag has been read.
+    pub fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        if !self.started {
+            self.started = true;
+            self.reader.begin();
+            return self.root().map(Some);
+        }
+        let at = self.reader.offset();
+        match self.stack.last_mut() {
+            None => Ok(None),
+            Some(Frame::Compound) => match self.reader.byte()? {
+                0 => Ok(Some(self.leave(Event::EndCompound))),
+                id => {
+                    let (id, name) = self.reader.named(id, at)?;
+                    self.start(id, Segment::Name(name.clone()), Some(name)).map(Some)
+                }
+            },
+            Some(Frame::List { len, next, .. }) if next == len => Ok(Some(self.leave(Event::EndList))),
+            Some(Frame::List { id, next, .. }) => {
+                let (id, i) = (*id, *next);
+                *next += 1;
+                self.start(id, Segment::Index(i), None).map(Some)
+            }
+        }
+    }
+
+    /// Skips the rest of the innermost compound or list without decoding
+    /// it, including its end event. Does nothing once the root is done.
+    pub fn skip_to_end(&mut self) -> Result<(), Error> {
+        match self.stack.last() {
+            None => return Ok(()),
+            Some(Frame::Compound) => self.reader.skip_compound()?,
+            Some(&Frame::List { id, len, next }) => {
+                self.reader.skip_elements(id, len - next)?;
+                self.reader.leave();
+            }
+        }
+        self.pop();
+        Ok(())
+    }
+
+    fn root(&mut self) -> Result<Event, Error> {
+        if self.reader.options().nameless_root {
+            let at = self.reader.offset();
+            return match self.reader.byte()? {
+                0 => Ok(Event::Value(None, Payload::End)),
+                id @ 1..=12 => self.payload(id, None),
+                id => Err(self.reader.fail(Reason::UnknownTag(id), at)),
+            };
+        }
+        let (id, name) = self.reader.header()?;
+        self.payload(id, Some(name))
+    }
+
+    /// Starts the payload of a child at `segment` of the current compound
+    /// or list.
+    fn start(&mut self, id: i8, segment: Segment, name: Option<String>) -> Result<Event, Error> {
+        self.reader.path_mut().push(segment);
+        let event = self.payload(id, name)?;
+        if let Event::Value(..) = event {
+            self.reader.path_mut().pop();
+        }
+        Ok(event)
+    }
+
+    fn payload(&mut self, id: i8, name: Option<String>) -> Result<Event, Error> {
+        let at = self.reader.offset();
+        match id {
+            9 => {
+                let (id, len) = self.reader.list_header()?;
+                self.stack.push(Frame::List { id, len, next: 0 });
+                Ok(Event::StartList(name, id, len))
+            }
+            10 => {
+                self.reader.enter(at)?;
+                self.stack.push(Frame::Compound);
+                Ok(Event::StartCompound(name))
+            }
+            _ => Ok(Event::Value(name, self.reader.payload(id)?)),
+        }
+    }
+
+    /// Finishes the innermost compound or list.
+    fn leave(&mut self, event: Event) -> Event {
+        self.reader.leave();
+        self.pop();
+        event
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+        if !self.stack.is_empty() {
+            self.reader.path_mut().pop();
+        }
+    }
+}
+
+/// Stops after the first error.
+impl<R: Read> Iterator for Events<R> {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.next_event().transpose();
+        if let Some(Err(_)) = event {
+            self.stack.clear();
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tag::Tag;
+    use crate::writer;
+
+    fn data() -> Vec<u8> {
+        let root = Tag::new("", Payload::Compound(vec![
+            Tag::new("id", Payload::String("minecraft:chest".into())),
+            Tag::new("Items", Payload::list(vec![
+                Payload::Compound(vec![Tag::new("id", Payload::String("minecraft:stone".into()))]),
+                Payload::Compound(vec![Tag::new("Count", Payload::Byte(3))]),
+            ])),
+            Tag::new("Pos", Payload::IntArray(vec![1, 2, 3])),
+        ]));
+        let mut data = Vec::new();
+        writer::tag(&mut data, &root).unwrap();
+        data
+    }
+
+    #[test]
+    fn events() {
+        let data = data();
+        let events: Vec<_> = Events::new(&data[..], Options::default()).collect::<Result<_, _>>().unwrap();
+        let name = |s: &str| Some(s.to_string());
+        assert_eq!(events, [
+            Event::StartCompound(name("")),
+            Event::Value(name("id"), Payload::String("minecraft:chest".into())),
+            Event::StartList(name("Items"), 10, 2),
+            Event::StartCompound(None),
+            Event::Value(name("id"), Payload::String("minecraft:stone".into())),
+            Event::EndCompound,
+            Event::StartCompound(None),
+            Event::Value(name("Count"), Payload::Byte(3)),
+            Event::EndCompound,
+            Event::EndList,
+            Event::Value(name("Pos"), Payload::IntArray(vec![1, 2, 3])),
+            Event::EndCompound,
+        ]);
+
+        let ids: Vec<_> = Events::new(&data[..], Options::default())
+            .filter_map(|e| match e.unwrap() {
+                Event::Value(Some(name), Payload::String(s)) if name == "id" => Some(s),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, ["minecraft:chest", "minecraft:stone"]);
+    }
+
+    #[test]
+    fn skip() {
+        let data = data();
+        let mut events = Events::new(&data[..], Options::default());
+        let mut seen = Vec::new();
+        while let Some(event) = events.next_event().unwrap() {
+            if let Event::StartList(..) = event {
+                events.skip_to_end().unwrap();
+            }
+            seen.push(event);
+        }
+        assert_eq!(seen.len(), 5);
+        assert_eq!(seen[3], Event::Value(Some("Pos".into()), Payload::IntArray(vec![1, 2, 3])));
+        assert_eq!(events.offset(), data.len());
+
+        // Skip the first list element's rest, then read the second one
+        let mut events = Events::new(&data[..], Options::default());
+        for _ in 0..4 {
+            events.next_event().unwrap();
+        }
+        events.skip_to_end().unwrap();
+        assert_eq!(events.next_event().unwrap(), Some(Event::StartCompound(None)));
+        assert_eq!(events.next_event().unwrap(), Some(Event::Value(Some("Count".into()), Payload::Byte(3))));
+    }
+
+    #[test]
+    fn nameless() {
+        let opts = Options { nameless_root: true, ..Options::default() };
+        let events: Vec<_> = Events::new(&[0x08, 0x00, 0x01, b'x'][..], opts).collect::<Result<_, _>>().unwrap();
+        assert_eq!(events, [Event::Value(None, Payload::String("x".into()))]);
+    }
+
+    #[test]
+    fn errors() {
+        let data = data();
+        let mut events = Events::new(&data[..50], Options::default());
+        let e = events.find_map(Result::err).unwrap();
+        assert_eq!(e.path().to_string(), "Items[0].id");
+        assert!(events.next().is_none());
+
+        let deep = crate::parser::Limits { depth: 1, ..Default::default() };
+        let opts = Options { limits: deep, ..Options::default() };
+        let e = Events::new(&data[..], opts).find_map(Result::err).unwrap();
+        assert_eq!((e.reason(), e.path().to_string()), (&Reason::DepthLimit, "Items".into()));
+    }
+}
@@ -0,0 +1,238 @@
+//! Whole NBT files such as `level.dat` or player data.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::compression::Compression;
+use crate::error::{self, Error, Reason};
+use crate::parser::tag::Tag;
+use crate::parser::{self, Encoding, Options};
+use crate::stream::Reader;
+use crate::writer;
+
+/// A parsed NBT file: its named root compound and how it was stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NbtFile {
+    pub root: Tag,
+    pub compression: Compression,
+    pub encoding: Encoding,
+    /// Storage version from the header Bedrock puts in front of `level.dat`.
+    pub header: Option<i32>,
+    /// The root was stored without a name; see `Options::nameless_root`.
+    pub nameless: bool,
+}
+
+impl NbtFile {
+    pub fn new(root: Tag, compression: Compression) -> Self {
+        Self { root, compression, encoding: Encoding::default(), header: None, nameless: false }
+    }
+
+    /// Parses a file held in memory, decompressing it first if needed.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        Self::from_bytes_with(data, Options::default())
+    }
+
+    /// Parses a file with the given options. Little-endian files may start
+    /// with a Bedrock `level.dat` header, which is detected and kept.
+    pub fn from_bytes_with(data: &[u8], opts: Options) -> Result<Self, Error> {
+        let compression = Compression::detect(data);
+        let data = compression.decompress_limited(data, opts.limits.bytes)?;
+        if data.len() > opts.limits.bytes {
+            return Err(Error::new(Reason::SizeLimit, opts.limits.bytes, error::Path::default()));
+        }
+        let (header, data) = match opts.encoding {
+            Encoding::LittleEndian => bedrock_header(&data),
+            Encoding::BigEndian | Encoding::Network => (None, &data[..]),
+        };
+        let root = root(data, opts)?;
+        Ok(Self { root, compression, encoding: opts.encoding, header, nameless: opts.nameless_root })
+    }
+
+    pub fn read<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::read_with(reader, Options::default())
+    }
+
+    /// Parses a file as it is read, decompressing on the fly. Little-endian
+    /// files are read into memory first, to detect the Bedrock header.
+    pub fn read_with<R: Read>(reader: R, opts: Options) -> Result<Self, Error> {
+        let mut reader = BufReader::new(reader);
+        if opts.encoding == Encoding::LittleEndian {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            return Self::from_bytes_with(&data, opts);
+        }
+        let compression = Compression::detect(reader.fill_buf()?);
+        let mut stream = Reader::new(compression.decoder(reader)?, opts);
+        let root = stream_root(&mut stream, opts)?;
+        Ok(Self { root, compression, encoding: opts.encoding, header: None, nameless: opts.nameless_root })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::read(fs::File::open(path)?)
+    }
+
+    /// Serializes the file, compressed the same way it was read.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        if self.nameless {
+            writer::nameless_with(&mut data, &self.root.payload, self.encoding)?;
+        } else {
+            writer::tag_with(&mut data, &self.root, self.encoding)?;
+        }
+        if let Some(version) = self.header {
+            let len = i32::try_from(data.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large"))?;
+            data.splice(0..0, version.to_le_bytes().into_iter().chain(len.to_le_bytes()));
+        }
+        Ok(self.compression.compress(&data)?.into_owned())
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes()?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes()?)
+    }
+}
+
+/// Splits off the storage version and length that precede a Bedrock
+/// `level.dat`, if the data starts with them.
+fn bedrock_header(data: &[u8]) -> (Option<i32>, &[u8]) {
+    match data {
+        [v0, v1, v2, v3, l0, l1, l2, l3, body @ ..]
+            if body.first() == Some(&10) && i32::from_le_bytes([*l0, *l1, *l2, *l3]) as usize == body.len() =>
+        {
+            (Some(i32::from_le_bytes([*v0, *v1, *v2, *v3])), body)
+        }
+        _ => (None, data),
+    }
+}
+
+/// Parses the root tag of uncompressed data. A named root must be a compound.
+pub(crate) fn root(data: &[u8], opts: Options) -> Result<Tag, Error> {
+    match data.first() {
+        Some(&id) if id != 10 && !opts.nameless_root => {
+            Err(Error::new(Reason::RootNotCompound(id as i8), 0, error::Path::default()))
+        }
+        _ => parser::parse(data, opts).map(|(root, _)| root),
+    }
+}
+
+/// Like `root`, reading from a stream.
+fn stream_root<R: Read>(stream: &mut Reader<R>, opts: Options) -> Result<Tag, Error> {
+    if opts.nameless_root {
+        return stream.root();
+    }
+    let id = stream.byte()?;
+    if id != 10 {
+        return Err(Error::new(Reason::RootNotCompound(id), 0, error::Path::default()));
+    }
+    let (id, name) = stream.named(id, 0)?;
+    Ok(Tag::new(name, stream.payload(id)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::payload::Payload;
+    use crate::parser::Limits;
+
+    // compound "" { byte "b": 1 }
+    const PLAIN: &[u8] = &[0x0a, 0x00, 0x00, 0x01, 0x00, 0x01, 0x62, 0x01, 0x00];
+    const GZIP: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03,
+        0xe3, 0x62, 0x60, 0x60, 0x64, 0x60, 0x4c, 0x62, 0x64, 0x00, 0x00,
+        0xff, 0x04, 0x8f, 0x52, 0x09, 0x00, 0x00, 0x00,
+        ];
+    const ZLIB: &[u8] = &[
+        0x78, 0x9c, 0xe3, 0x62, 0x60, 0x60, 0x64, 0x60,
+        0x4c, 0x62, 0x64, 0x00, 0x00, 0x01, 0x95, 0x00, 0x70,
+        ];
+
+    fn root() -> Tag {
+        Tag::new("", Payload::Compound(vec![Tag::new("b", Payload::Byte(1))]))
+    }
+
+    #[test]
+    fn from_bytes() {
+        for (data, compression) in [(PLAIN, Compression::None), (GZIP, Compression::Gzip), (ZLIB, Compression::Zlib)] {
+            assert_eq!(NbtFile::from_bytes(data).unwrap(), NbtFile::new(root(), compression));
+        }
+    }
+
+    #[test]
+    fn read() {
+        for data in [PLAIN, GZIP, ZLIB] {
+            assert_eq!(NbtFile::read(data).unwrap(), NbtFile::from_bytes(data).unwrap());
+        }
+        let reason = |data: &[u8]| NbtFile::read(data).unwrap_err().reason().clone();
+        assert_eq!(reason(&[0x01, 0x00, 0x00, 0x01]), Reason::RootNotCompound(1));
+        assert_eq!(reason(&PLAIN[..7]), Reason::Truncated);
+        assert_eq!(reason(&[]), Reason::Truncated);
+    }
+
+    #[test]
+    fn to_bytes() {
+        assert_eq!(NbtFile::from_bytes(PLAIN).unwrap().to_bytes().unwrap(), PLAIN);
+        for data in [GZIP, ZLIB] {
+            let file = NbtFile::from_bytes(data).unwrap();
+            assert_eq!(NbtFile::from_bytes(&file.to_bytes().unwrap()).unwrap(), file);
+        }
+    }
+
+    #[test]
+    fn bedrock() {
+        let le = Options { encoding: Encoding::LittleEndian, ..Options::default() };
+        let body = [0x0a, 0x00, 0x00, 0x01, 0x01, 0x00, 0x62, 0x01, 0x00];
+        let mut level = vec![0x0a, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00];
+        level.extend(body);
+
+        let file = NbtFile::from_bytes_with(&level, le).unwrap();
+        assert_eq!(file.root, root());
+        assert_eq!((file.encoding, file.header), (Encoding::LittleEndian, Some(10)));
+        assert_eq!(file.to_bytes().unwrap(), level);
+
+        let file = NbtFile::from_bytes_with(&body, le).unwrap();
+        assert_eq!((file.root, file.header), (root(), None));
+    }
+
+    #[test]
+    fn nameless() {
+        let opts = Options { nameless_root: true, ..Options::default() };
+        let file = NbtFile::from_bytes_with(&[0x0a, 0x01, 0x00, 0x01, 0x62, 0x01, 0x00], opts).unwrap();
+        assert_eq!((&file.root, file.nameless), (&root(), true));
+        assert_eq!(file.to_bytes().unwrap(), [0x0a, 0x01, 0x00, 0x01, 0x62, 0x01, 0x00]);
+
+        let file = NbtFile::from_bytes_with(&[0x03, 0x00, 0x00, 0x00, 0x07], opts).unwrap();
+        assert_eq!(file.root.payload, Payload::Int(7));
+    }
+
+    #[test]
+    fn errors() {
+        let reason = |data: &[u8]| NbtFile::from_bytes(data).unwrap_err().reason().clone();
+        assert_eq!(reason(&[]), Reason::Truncated);
+        assert_eq!(reason(&[0x01, 0x00, 0x00, 0x01]), Reason::RootNotCompound(1));
+        assert_eq!(reason(&GZIP[..20]), Reason::Io);
+
+        let e = NbtFile::from_bytes(&PLAIN[..7]).unwrap_err();
+        assert_eq!((e.reason(), e.offset()), (&Reason::Truncated, 7));
+        assert_eq!(e.path().to_string(), "b");
+    }
+
+    #[test]
+    fn limits() {
+        let opts = |limits| Options { limits, ..Options::default() };
+        let small = Limits { bytes: 8, ..Limits::default() };
+        #[cfg(feature = "lz4")]
+        let lz4 = Compression::Lz4.compress(PLAIN).unwrap();
+        for data in [PLAIN, GZIP, ZLIB, #[cfg(feature = "lz4")] &lz4] {
+            assert_eq!(NbtFile::from_bytes_with(data, opts(small)).unwrap_err().reason(), &Reason::SizeLimit);
+            assert_eq!(NbtFile::read_with(data, opts(small)).unwrap_err().reason(), &Reason::SizeLimit);
+        }
+        let exact = Limits { bytes: 9, ..Limits::default() };
+        assert!(NbtFile::from_bytes_with(GZIP, opts(exact)).is_ok());
+        let flat = Limits { depth: 0, ..Limits::default() };
+        assert_eq!(NbtFile::from_bytes_with(PLAIN, opts(flat)).unwrap_err().reason(), &Reason::DepthLimit);
+    }
+}
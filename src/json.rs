@@ -0,0 +1,312 @@
+//! Converting payloads to and from JSON.
+//!
+//! `Mode::Typed` keeps everything needed to get the same payload back: every
+//! value becomes `{"type": "int", "value": 1}`, a compound an array of such
+//! objects with a `"name"` each, and a list also records its `"element"`
+//! type. Non-finite floats are written as the strings `NaN`, `Infinity` and
+//! `-Infinity`.
+//!
+//! `Mode::Human` maps to plain JSON instead: numbers, strings, arrays and
+//! objects. Reading it back picks the narrowest of int, long and double for
+//! numbers, turns booleans into bytes, and widens numbers in one array to a
+//! common type. Compound keys keep their order both ways.
+
+use serde::de::Error as _;
+use serde_json::{Map, Number, Value};
+
+use crate::error::{Error, Segment};
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Typed,
+    Human,
+}
+
+pub fn to_value(p: &Payload, mode: Mode) -> Value {
+    match mode {
+        Mode::Typed => typed(p),
+        Mode::Human => human(p),
+    }
+}
+
+pub fn from_value(v: &Value, mode: Mode) -> Result<Payload, Error> {
+    match mode {
+        Mode::Typed => from_typed(v),
+        Mode::Human => from_human(v),
+    }
+}
+
+const NAMES: [&str; 13] = [
+    "end", "byte", "short", "int", "long", "float", "double", "byte_array", "string", "list", "compound", "int_array",
+    "long_array",
+];
+
+fn name(id: i8) -> &'static str {
+    NAMES.get(id as usize).copied().unwrap_or("end")
+}
+
+fn id(name: &str) -> Option<i8> {
+    NAMES.iter().position(|&n| n == name).map(|i| i as i8)
+}
+
+fn typed(p: &Payload) -> Value {
+    let mut node = Map::new();
+    node.insert("type".into(), name(p.id()).into());
+    if let Payload::List(id, _) = p {
+        node.insert("element".into(), name(*id).into());
+    }
+    let value = match p {
+        Payload::End => Value::Null,
+        Payload::Byte(x) => (*x).into(),
+        Payload::Short(x) => (*x).into(),
+        Payload::Int(x) => (*x).into(),
+        Payload::Long(x) => (*x).into(),
+        Payload::Float(x) => float(*x as f64),
+        Payload::Double(x) => float(*x),
+        Payload::ByteArray(x) => x.iter().map(|&b| b as i8).collect(),
+        Payload::String(x) => x.as_str().into(),
+        Payload::List(_, x) => x.iter().map(typed).collect(),
+        Payload::Compound(x) => x
+            .iter()
+            .map(|t| {
+                let mut entry = typed(&t.payload);
+                entry.as_object_mut().unwrap().insert("name".into(), t.name.as_str().into());
+                entry
+            })
+            .collect(),
+        Payload::IntArray(x) => x.as_slice().into(),
+        Payload::LongArray(x) => x.as_slice().into(),
+    };
+    node.insert("value".into(), value);
+    node.into()
+}
+
+/// A number, or a string for values JSON numbers cannot hold.
+fn float(x: f64) -> Value {
+    match Number::from_f64(x) {
+        Some(n) => n.into(),
+        None if x.is_nan() => "NaN".into(),
+        None if x > 0.0 => "Infinity".into(),
+        None => "-Infinity".into(),
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::custom(msg)
+}
+
+fn from_typed(v: &Value) -> Result<Payload, Error> {
+    let kind = v.get("type").and_then(Value::as_str).ok_or_else(|| invalid("expected an object with a type"))?;
+    let value = v.get("value").ok_or_else(|| invalid("expected an object with a value"))?;
+    let p = match kind {
+        "end" if value.is_null() => Payload::End,
+        "end" => return Err(invalid("expected null")),
+        "byte" => Payload::Byte(integer(value, "expected a byte")?),
+        "short" => Payload::Short(integer(value, "expected a short")?),
+        "int" => Payload::Int(integer(value, "expected an int")?),
+        "long" => Payload::Long(integer(value, "expected a long")?),
+        "float" => Payload::Float(from_float(value)? as f32),
+        "double" => Payload::Double(from_float(value)?),
+        "string" => Payload::String(value.as_str().ok_or_else(|| invalid("expected a string"))?.into()),
+        "byte_array" => Payload::ByteArray(array(value, |x| i8::try_from(x).ok().map(|b| b as u8), "expected a byte")?),
+        "int_array" => Payload::IntArray(array(value, |x| i32::try_from(x).ok(), "expected an int")?),
+        "long_array" => Payload::LongArray(array(value, Some, "expected a long")?),
+        "list" => {
+            let element = v.get("element").and_then(Value::as_str).and_then(id);
+            let element = element.ok_or_else(|| invalid("expected a list with an element type"))?;
+            let items = elements(value)?.iter().enumerate().map(|(i, item)| match from_typed(item) {
+                Ok(p) if p.id() == element => Ok(p),
+                Ok(_) => Err(invalid("list element does not match the list type").within(Segment::Index(i))),
+                Err(e) => Err(e.within(Segment::Index(i))),
+            });
+            Payload::List(element, items.collect::<Result<_, _>>()?)
+        }
+        "compound" => {
+            let tags = elements(value)?.iter().map(|entry| {
+                let name = entry.get("name").and_then(Value::as_str).ok_or_else(|| invalid("expected a named tag"))?;
+                let payload = from_typed(entry).map_err(|e| e.within(Segment::Name(name.into())))?;
+                Ok(Tag::new(name, payload))
+            });
+            Payload::Compound(tags.collect::<Result<_, Error>>()?)
+        }
+        _ => return Err(invalid("unknown type")),
+    };
+    Ok(p)
+}
+
+fn integer<T: TryFrom<i64>>(v: &Value, what: &str) -> Result<T, Error> {
+    v.as_i64().and_then(|x| x.try_into().ok()).ok_or_else(|| invalid(what))
+}
+
+fn from_float(v: &Value) -> Result<f64, Error> {
+    match v {
+        Value::Number(n) => n.as_f64().ok_or_else(|| invalid("expected a number")),
+        Value::String(s) if s == "NaN" => Ok(f64::NAN),
+        Value::String(s) if s == "Infinity" => Ok(f64::INFINITY),
+        Value::String(s) if s == "-Infinity" => Ok(f64::NEG_INFINITY),
+        _ => Err(invalid("expected a number")),
+    }
+}
+
+fn elements(v: &Value) -> Result<&Vec<Value>, Error> {
+    v.as_array().ok_or_else(|| invalid("expected an array"))
+}
+
+fn array<T>(v: &Value, f: impl Fn(i64) -> Option<T>, what: &str) -> Result<Vec<T>, Error> {
+    let items = elements(v)?.iter().enumerate();
+    items.map(|(i, x)| x.as_i64().and_then(&f).ok_or_else(|| invalid(what).within(Segment::Index(i)))).collect()
+}
+
+fn human(p: &Payload) -> Value {
+    match p {
+        Payload::End => Value::Null,
+        Payload::Byte(x) => (*x).into(),
+        Payload::Short(x) => (*x).into(),
+        Payload::Int(x) => (*x).into(),
+        Payload::Long(x) => (*x).into(),
+        // Through the shortest decimal form, so 0.1f stays 0.1
+        Payload::Float(x) => Number::from_f64(x.to_string().parse().unwrap_or(f64::NAN)).map_or(Value::Null, Value::Number),
+        Payload::Double(x) => Number::from_f64(*x).map_or(Value::Null, Value::Number),
+        Payload::ByteArray(x) => x.iter().map(|&b| b as i8).collect(),
+        Payload::String(x) => x.as_str().into(),
+        Payload::List(_, x) => x.iter().map(human).collect(),
+        Payload::Compound(x) => x.iter().map(|t| (t.name.clone(), human(&t.payload))).collect::<Map<_, _>>().into(),
+        Payload::IntArray(x) => x.as_slice().into(),
+        Payload::LongArray(x) => x.as_slice().into(),
+    }
+}
+
+fn from_human(v: &Value) -> Result<Payload, Error> {
+    let p = match v {
+        Value::Null => return Err(invalid("null has no NBT equivalent")),
+        Value::Bool(b) => Payload::Byte(*b as i8),
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(x), _) => i32::try_from(x).map_or(Payload::Long(x), Payload::Int),
+            (None, Some(x)) => Payload::Double(x),
+            (None, None) => return Err(invalid("number out of range")),
+        },
+        Value::String(s) => Payload::String(s.clone()),
+        Value::Array(items) => {
+            let mut items = items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| from_human(item).map_err(|e| e.within(Segment::Index(i))))
+                .collect::<Result<Vec<_>, _>>()?;
+            widen(&mut items);
+            let id = items.first().map_or(0, Payload::id);
+            if let Some(i) = items.iter().position(|p| p.id() != id) {
+                return Err(invalid("array elements must have the same type").within(Segment::Index(i)));
+            }
+            Payload::List(id, items)
+        }
+        Value::Object(map) => {
+            let tags = map.iter().map(|(name, v)| match from_human(v) {
+                Ok(payload) => Ok(Tag::new(name.clone(), payload)),
+                Err(e) => Err(e.within(Segment::Name(name.clone()))),
+            });
+            Payload::Compound(tags.collect::<Result<_, _>>()?)
+        }
+    };
+    Ok(p)
+}
+
+/// Converts numbers to the widest type among them, if all items are numbers.
+fn widen(items: &mut [Payload]) {
+    if !items.iter().all(|p| matches!(p, Payload::Int(_) | Payload::Long(_) | Payload::Double(_))) {
+        return;
+    }
+    let widest = items.iter().map(Payload::id).max().unwrap_or(0);
+    for p in items {
+        *p = match (widest, &*p) {
+            (4, Payload::Int(x)) => Payload::Long(*x as i64),
+            (6, Payload::Int(x)) => Payload::Double(*x as f64),
+            (6, Payload::Long(x)) => Payload::Double(*x as f64),
+            _ => continue,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn level() -> Payload {
+        Payload::Compound(vec![
+            Tag::new("name", Payload::String("world".into())),
+            Tag::new("b", Payload::Byte(-1)),
+            Tag::new("s", Payload::Short(300)),
+            Tag::new("time", Payload::Long(i64::MAX)),
+            Tag::new("f", Payload::Float(0.1)),
+            Tag::new("d", Payload::Double(f64::INFINITY)),
+            Tag::new("empty", Payload::List(3, vec![])),
+            Tag::new("pos", Payload::list(vec![Payload::Double(0.5), Payload::Double(1.0)])),
+            Tag::new("nested", Payload::list(vec![Payload::list(vec![Payload::Byte(1)]), Payload::List(0, vec![])])),
+            Tag::new("blocks", Payload::ByteArray(vec![0, 200])),
+            Tag::new("uuid", Payload::IntArray(vec![1, -2])),
+            Tag::new("states", Payload::LongArray(vec![])),
+            Tag::new("z", Payload::Compound(vec![])),
+        ])
+    }
+
+    #[test]
+    fn typed() {
+        let v = to_value(&level(), Mode::Typed);
+        assert_eq!(v["value"][1], json!({"name": "b", "type": "byte", "value": -1}));
+        assert_eq!(v["value"][5]["value"], "Infinity");
+        assert_eq!(v["value"][6], json!({"name": "empty", "type": "list", "element": "int", "value": []}));
+        assert_eq!(from_value(&v, Mode::Typed).unwrap(), level());
+
+        let text = serde_json::to_string(&v).unwrap();
+        let parsed: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(from_value(&parsed, Mode::Typed).unwrap(), level());
+
+        let end = Payload::Compound(vec![Tag::new("none", Payload::End), Tag::new("l", Payload::List(0, vec![]))]);
+        let v = to_value(&end, Mode::Typed);
+        assert_eq!(v["value"][0], json!({"name": "none", "type": "end", "value": null}));
+        assert_eq!(v["value"][1]["element"], "end");
+        assert_eq!(from_value(&v, Mode::Typed).unwrap(), end);
+    }
+
+    #[test]
+    fn human() {
+        let v = to_value(&level(), Mode::Human);
+        assert_eq!(v["f"], json!(0.1));
+        assert_eq!(v["d"], Value::Null);
+        assert_eq!(v["nested"], json!([[1], []]));
+        assert_eq!(v["blocks"], json!([0, -56]));
+        let keys: Vec<_> = v.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys[..4], ["name", "b", "s", "time"]);
+        let back = from_value(&serde_json::from_str(r#"{"z": 1, "a": 2}"#).unwrap(), Mode::Human).unwrap();
+        assert_eq!(back, Payload::Compound(vec![Tag::new("z", Payload::Int(1)), Tag::new("a", Payload::Int(2))]));
+
+        let p = from_value(&json!({"a": [1, 5000000000i64, 2], "b": [1, 2.5], "c": true, "d": {"e": "x"}}), Mode::Human).unwrap();
+        assert_eq!(p, Payload::Compound(vec![
+            Tag::new("a", Payload::list(vec![Payload::Long(1), Payload::Long(5_000_000_000), Payload::Long(2)])),
+            Tag::new("b", Payload::list(vec![Payload::Double(1.0), Payload::Double(2.5)])),
+            Tag::new("c", Payload::Byte(1)),
+            Tag::new("d", Payload::Compound(vec![Tag::new("e", Payload::String("x".into()))])),
+        ]));
+    }
+
+    #[test]
+    fn errors() {
+        let e = from_value(&json!({"a": [{"b": null}]}), Mode::Human).unwrap_err();
+        assert_eq!(e.to_string(), "null has no NBT equivalent in a[0].b");
+        let e = from_value(&json!({"a": [1, "x"]}), Mode::Human).unwrap_err();
+        assert_eq!(e.path().to_string(), "a[1]");
+
+        let bad = json!({"type": "compound", "value": [
+            {"name": "l", "type": "list", "element": "int", "value": [{"type": "int", "value": 1}, {"type": "byte", "value": 1}]},
+        ]});
+        let e = from_value(&bad, Mode::Typed).unwrap_err();
+        assert_eq!(e.to_string(), "list element does not match the list type in l[1]");
+        let e = from_value(&json!({"type": "byte", "value": 128}), Mode::Typed).unwrap_err();
+        assert_eq!(e.to_string(), "expected a byte");
+        let e = from_value(&json!({"type": "end", "value": 0}), Mode::Typed).unwrap_err();
+        assert_eq!(e.to_string(), "expected null");
+    }
+}
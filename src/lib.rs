@@ -1 +1,25 @@
+#[cfg(feature = "serde")]
+pub mod array;
+pub mod chunk;
+pub mod compression;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod error;
+pub mod events;
+pub mod file;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "lz4")]
+mod lz4;
+pub mod mutf8;
 pub mod parser;
+pub mod region;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod snbt;
+pub mod stream;
+pub mod writer;
+
+pub use compression::Compression;
+pub use error::Error;
+pub use file::NbtFile;
@@ -0,0 +1,232 @@
+//! The LZ4 stream format of lz4-java's `LZ4BlockOutputStream`, which
+//! Minecraft uses for LZ4 compressed chunks.
+//!
+//! The stream is a series of blocks of at most 64 KiB of input, each with a
+//! header of the magic `LZ4Block`, a token holding the method and level, the
+//! compressed and original lengths and a checksum of the original data, all
+//! little-endian. An empty block ends the stream.
+//!
+//! Only this framing is handled here: the blocks themselves are coded with
+//! `lz4_flex`, whose safe decoder checks every length and offset against the
+//! data as untrusted chunks need, and checksummed with `twox-hash`.
+
+use std::io::{self, Read};
+
+use twox_hash::XxHash32;
+
+const MAGIC: &[u8] = b"LZ4Block";
+const HEADER: usize = MAGIC.len() + 13;
+const RAW: u8 = 0x10;
+const LZ4: u8 = 0x20;
+const BLOCK: usize = 1 << 16;
+/// `log2(BLOCK) - 10`, as lz4-java records it.
+const LEVEL: u8 = 6;
+const SEED: u32 = 0x9747_B28C;
+
+/// Decompresses a stream, stopping after the block that takes the output
+/// past `max` bytes. A stream that ends without the empty block is accepted.
+pub(crate) fn decompress(mut data: &[u8], max: usize, out: &mut Vec<u8>) -> io::Result<()> {
+    while !data.is_empty() && out.len() <= max {
+        let head = data.get(..HEADER).ok_or(io::ErrorKind::UnexpectedEof)?;
+        let Some(block) = Block::parse(head)? else { return Ok(()) };
+        let body = data.get(HEADER..HEADER + block.packed).ok_or(io::ErrorKind::UnexpectedEof)?;
+        block.unpack(body, out)?;
+        data = &data[HEADER + block.packed..];
+    }
+    Ok(())
+}
+
+/// Decompresses a stream as it is read, holding one block at a time.
+pub(crate) struct Decoder<R> {
+    inner: R,
+    body: Vec<u8>,
+    block: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Decoder { inner, body: Vec::new(), block: Vec::new(), pos: 0, done: false }
+    }
+
+    /// Decodes the next block into `self.block`, or marks the end.
+    fn next(&mut self) -> io::Result<()> {
+        let mut head = Vec::with_capacity(HEADER);
+        self.inner.by_ref().take(HEADER as u64).read_to_end(&mut head)?;
+        let block = match head.len() {
+            0 => None,
+            HEADER => Block::parse(&head)?,
+            _ => return Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+        let Some(block) = block else {
+            self.done = true;
+            return Ok(());
+        };
+        self.body.clear();
+        self.inner.by_ref().take(block.packed as u64).read_to_end(&mut self.body)?;
+        if self.body.len() < block.packed {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.block.clear();
+        self.pos = 0;
+        block.unpack(&self.body, &mut self.block)
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() && !self.done {
+            self.next()?;
+        }
+        let n = buf.len().min(self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The header in front of a block.
+struct Block {
+    method: u8,
+    packed: usize,
+    len: usize,
+    check: u32,
+}
+
+impl Block {
+    /// Reads a header of `HEADER` bytes, `None` for the empty block ending
+    /// the stream. Lengths past what the block size in the token allows are
+    /// rejected, as lz4-java does, so a header cannot claim gigabytes.
+    fn parse(head: &[u8]) -> io::Result<Option<Self>> {
+        if &head[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not an LZ4Block stream"));
+        }
+        let token = head[MAGIC.len()];
+        let int = |i: usize| {
+            let at = MAGIC.len() + 1 + i * 4;
+            u32::from_le_bytes([head[at], head[at + 1], head[at + 2], head[at + 3]])
+        };
+        let (packed, len, check) = (int(0) as usize, int(1) as usize, int(2));
+        if packed == 0 && len == 0 {
+            return Ok(None);
+        }
+        let size = 1 << (10 + (token & 0x0F));
+        if len > size || packed > len + len / 255 + 16 {
+            return Err(invalid("invalid LZ4Block header"));
+        }
+        Ok(Some(Block { method: token & 0xF0, packed, len, check }))
+    }
+
+    /// Decodes the block's `body`, appending it to `out`.
+    fn unpack(&self, body: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        let start = out.len();
+        match self.method {
+            RAW if self.packed == self.len => out.extend_from_slice(body),
+            LZ4 => {
+                out.resize(start + self.len, 0);
+                match lz4_flex::block::decompress_into(body, &mut out[start..]) {
+                    Ok(n) if n == self.len => {}
+                    _ => return Err(invalid("invalid LZ4 block")),
+                }
+            }
+            _ => return Err(invalid("invalid LZ4Block header")),
+        }
+        if checksum(&out[start..]) != self.check {
+            return Err(invalid("LZ4Block checksum mismatch"));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for chunk in data.chunks(BLOCK) {
+        let packed = lz4_flex::block::compress(chunk);
+        let (method, body) = match packed.len() < chunk.len() {
+            true => (LZ4, &packed[..]),
+            false => (RAW, chunk),
+        };
+        header(&mut out, method, body.len(), chunk.len(), checksum(chunk));
+        out.extend_from_slice(body);
+    }
+    header(&mut out, RAW, 0, 0, 0);
+    out
+}
+
+fn header(out: &mut Vec<u8>, method: u8, packed: usize, len: usize, check: u32) {
+    out.extend_from_slice(MAGIC);
+    out.push(method | LEVEL);
+    for n in [packed as u32, len as u32, check] {
+        out.extend(n.to_le_bytes());
+    }
+}
+
+/// lz4-java's checksum keeps only the low 28 bits of the hash.
+fn checksum(data: &[u8]) -> u32 {
+    XxHash32::oneshot(SEED, data) & 0x0FFF_FFFF
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block() {
+        // A literal, a match overlapping what it repeats, and a last literal
+        let mut data = header_bytes(LZ4 | LEVEL, 6, 10, checksum(b"aaaaaaaaab"));
+        data.extend([0x14, b'a', 1, 0, 0x10, b'b']);
+        let mut out = Vec::new();
+        decompress(&data, usize::MAX, &mut out).unwrap();
+        assert_eq!(out, b"aaaaaaaaab");
+    }
+
+    #[test]
+    fn round_trip() {
+        let text: Vec<u8> = (0..200_000u32).flat_map(|i| (i / 7).to_le_bytes()).collect();
+        let noise: Vec<u8> = (0..1000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        for data in [&text[..], &noise, b"hi", b""] {
+            let packed = compress(data);
+            let mut out = Vec::new();
+            decompress(&packed, usize::MAX, &mut out).unwrap();
+            assert_eq!(out, data);
+        }
+        assert!(compress(&text).len() < text.len() / 2);
+        assert_eq!(compress(b"hi")[8], RAW | LEVEL);
+        assert_eq!(compress(b"")[..], header_bytes(RAW, 0, 0, 0));
+
+        let mut out = Vec::new();
+        decompress(&compress(&text), 10, &mut out).unwrap();
+        assert_eq!(out.len(), BLOCK);
+    }
+
+    fn header_bytes(method: u8, packed: usize, len: usize, check: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        header(&mut out, method, packed, len, check);
+        out
+    }
+
+    #[test]
+    fn corrupt() {
+        let packed = compress(&[7; 1000]);
+        let mut out = Vec::new();
+        assert!(decompress(&packed[..30], usize::MAX, &mut out).is_err());
+        for at in [0, HEADER + 3, HEADER - 1] {
+            let mut bad = packed.clone();
+            bad[at] ^= 0x40;
+            assert!(decompress(&bad, usize::MAX, &mut Vec::new()).is_err(), "{}", at);
+        }
+        // A match reaching back before the start of the block
+        let mut bad = header_bytes(LZ4, 4, 8, 0);
+        bad.extend([0x10, b'a', 9, 0]);
+        assert!(decompress(&bad, usize::MAX, &mut Vec::new()).is_err());
+        // A block longer than its level allows
+        let huge = header_bytes(LZ4 | LEVEL, 16, 1 << 30, 0);
+        let e = Decoder::new(&huge[..]).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,217 @@
+//! Java's Modified UTF-8, the string encoding used by Java Edition NBT.
+//!
+//! It differs from standard UTF-8 in two ways: NUL is written as the two
+//! byte sequence `C0 80`, and characters outside the Basic Multilingual Plane
+//! are written as a surrogate pair, each half encoded on its own as three
+//! bytes (CESU-8). Four byte sequences never appear.
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// Error returned when a byte sequence is not valid Modified UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    valid_up_to: usize,
+}
+
+impl Error {
+    /// Index in the input up to which the bytes were valid.
+    pub fn valid_up_to(&self) -> usize {
+        self.valid_up_to
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid modified utf-8 sequence at byte {}", self.valid_up_to)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decodes `bytes`, failing on the first invalid sequence.
+///
+/// Borrows the input when it is also valid standard UTF-8.
+pub fn decode(bytes: &[u8]) -> Result<Cow<'_, str>, Error> {
+    if let Some(s) = plain(bytes) {
+        return Ok(Cow::Borrowed(s));
+    }
+    let mut out = String::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match next(bytes, i) {
+            Some((c, n)) => {
+                out.push(c);
+                i += n;
+            }
+            None => return Err(Error { valid_up_to: i }),
+        }
+    }
+    Ok(Cow::Owned(out))
+}
+
+/// Decodes `bytes`, replacing every invalid sequence with U+FFFD.
+pub fn decode_lossy(bytes: &[u8]) -> Cow<'_, str> {
+    if let Some(s) = plain(bytes) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match next(bytes, i) {
+            Some((c, n)) => {
+                out.push(c);
+                i += n;
+            }
+            None => {
+                // Skip a whole unpaired surrogate rather than each of its bytes
+                out.push(char::REPLACEMENT_CHARACTER);
+                i += unit(bytes, i).map_or(1, |(_, n)| n);
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Encodes `s`, borrowing it when no byte needs to change.
+pub fn encode(s: &str) -> Cow<'_, [u8]> {
+    if !s.bytes().any(|b| b == 0 || b >= 0xF0) {
+        return Cow::Borrowed(s.as_bytes());
+    }
+    let mut out = Vec::with_capacity(encoded_len(s));
+    for c in s.chars() {
+        match c as u32 {
+            0 => out.extend([0xC0, 0x80]),
+            0x10000.. => {
+                let mut units = [0u16; 2];
+                c.encode_utf16(&mut units);
+                units.iter().for_each(|&u| push_unit(&mut out, u));
+            }
+            _ => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Number of bytes `s` takes once encoded.
+pub fn encoded_len(s: &str) -> usize {
+    s.chars()
+        .map(|c| match c as u32 {
+            0 => 2,
+            0x10000.. => 6,
+            _ => c.len_utf8(),
+        })
+        .sum()
+}
+
+/// Returns the input as a `str` when it needs no translation: valid UTF-8
+/// that contains neither an encoded NUL nor a surrogate half.
+fn plain(bytes: &[u8]) -> Option<&str> {
+    if bytes.iter().any(|&b| b == 0xC0 || b == 0xED || b >= 0xF0) {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()
+}
+
+/// Decodes the character starting at `i`, returning it with its length.
+fn next(bytes: &[u8], i: usize) -> Option<(char, usize)> {
+    let (high, n) = unit(bytes, i)?;
+    match high {
+        0xD800..=0xDBFF => {
+            let (low, m) = unit(bytes, i + n)?;
+            if !(0xDC00..=0xDFFF).contains(&low) {
+                return None;
+            }
+            let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+            char::from_u32(c).map(|c| (c, n + m))
+        }
+        _ => char::from_u32(high).map(|c| (c, n)),
+    }
+}
+
+/// Decodes the one, two or three byte sequence starting at `i` into a UTF-16
+/// code unit.
+fn unit(bytes: &[u8], i: usize) -> Option<(u32, usize)> {
+    let cont = |j: usize| bytes.get(i + j).filter(|&&b| b & 0xC0 == 0x80).map(|&b| (b & 0x3F) as u32);
+    let b = *bytes.get(i)? as u32;
+    match b {
+        0x00..=0x7F => Some((b, 1)),
+        0xC0..=0xDF => {
+            let u = ((b & 0x1F) << 6) | cont(1)?;
+            // Overlong forms are only allowed for the encoded NUL.
+            (u >= 0x80 || u == 0).then_some((u, 2))
+        }
+        0xE0..=0xEF => {
+            let u = ((b & 0x0F) << 12) | (cont(1)? << 6) | cont(2)?;
+            (u >= 0x800).then_some((u, 3))
+        }
+        _ => None,
+    }
+}
+
+fn push_unit(out: &mut Vec<u8>, u: u16) {
+    out.extend([
+        0xE0 | (u >> 12) as u8,
+        0x80 | ((u >> 6) & 0x3F) as u8,
+        0x80 | (u & 0x3F) as u8,
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // U+1F600 as a CESU-8 surrogate pair
+    const GRIN: &[u8] = &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80];
+
+    #[test]
+    fn ascii_is_borrowed() {
+        assert!(matches!(decode(b"hello world"), Ok(Cow::Borrowed("hello world"))));
+        assert!(matches!(encode("hello world"), Cow::Borrowed(b"hello world")));
+    }
+
+    #[test]
+    fn nul() {
+        assert_eq!(decode(&[0x61, 0xC0, 0x80, 0x62]).unwrap(), "a\0b");
+        assert_eq!(encode("a\0b"), &[0x61, 0xC0, 0x80, 0x62][..]);
+    }
+
+    #[test]
+    fn bmp() {
+        let s = "é€";
+        assert_eq!(decode(s.as_bytes()).unwrap(), s);
+        assert_eq!(encode(s), s.as_bytes());
+    }
+
+    #[test]
+    fn supplementary() {
+        assert_eq!(decode(GRIN).unwrap(), "\u{1F600}");
+        assert_eq!(encode("\u{1F600}"), GRIN);
+        assert_eq!(encoded_len("a\u{1F600}\0"), 9);
+    }
+
+    #[test]
+    fn round_trip() {
+        let s = "Diamond Sword \u{1F5E1}\0\u{2764}";
+        assert_eq!(decode(&encode(s)).unwrap(), s);
+    }
+
+    #[test]
+    fn strict() {
+        // Four byte UTF-8 is not Modified UTF-8
+        assert_eq!(decode("\u{1F600}".as_bytes()), Err(Error { valid_up_to: 0 }));
+        // Unpaired high surrogate
+        assert_eq!(decode(&[0x61, 0xED, 0xA0, 0xBD, 0x62]), Err(Error { valid_up_to: 1 }));
+        // Truncated sequence
+        assert_eq!(decode(&[0x61, 0x62, 0xE2, 0x82]), Err(Error { valid_up_to: 2 }));
+        // Overlong
+        assert_eq!(decode(&[0xC1, 0x81]), Err(Error { valid_up_to: 0 }));
+    }
+
+    #[test]
+    fn lossy() {
+        assert_eq!(decode_lossy(&[0x61, 0xED, 0xA0, 0xBD, 0x62]), "a\u{FFFD}b");
+        assert_eq!(decode_lossy(&[0x61, 0xFF, 0x62]), "a\u{FFFD}b");
+        assert_eq!(decode_lossy(GRIN), "\u{1F600}");
+    }
+}
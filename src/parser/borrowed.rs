@@ -0,0 +1,393 @@
+//! Zero-copy parsing. Names, strings and arrays stay views into the input,
+//! so scanning many tags allocates little more than the tree itself.
+//! The owned parsers are built on these, copying what they return.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+use nom::bytes::complete::take;
+use nom::combinator::map;
+
+use crate::error::{self, Error, Reason, Segment};
+use crate::parser::payload::{self, Scope};
+use crate::parser::{raw, tag, Encoding, IResult, Options};
+
+/// A payload borrowing from the input; see `parser::payload::Payload`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload<'a> {
+    End,
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(&'a [u8]),
+    /// Borrowed unless decoding had to change the bytes.
+    String(Cow<'a, str>),
+    List(i8, Vec<Payload<'a>>),
+    Compound(Vec<Tag<'a>>),
+    IntArray(Array<'a, i32>),
+    LongArray(Array<'a, i64>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag<'a> {
+    pub name: Cow<'a, str>,
+    pub payload: Payload<'a>,
+}
+
+impl Payload<'_> {
+    pub fn id(&self) -> i8 {
+        use Payload::*;
+        match self {
+            End => 0,
+            Byte(_) => 1,
+            Short(_) => 2,
+            Int(_) => 3,
+            Long(_) => 4,
+            Float(_) => 5,
+            Double(_) => 6,
+            ByteArray(_) => 7,
+            String(_) => 8,
+            List(..) => 9,
+            Compound(_) => 10,
+            IntArray(_) => 11,
+            LongArray(_) => 12,
+        }
+    }
+
+    /// Copies into an owned payload.
+    pub fn to_owned(&self) -> payload::Payload {
+        use payload::Payload as P;
+        match self {
+            Payload::End => P::End,
+            Payload::Byte(x) => P::Byte(*x),
+            Payload::Short(x) => P::Short(*x),
+            Payload::Int(x) => P::Int(*x),
+            Payload::Long(x) => P::Long(*x),
+            Payload::Float(x) => P::Float(*x),
+            Payload::Double(x) => P::Double(*x),
+            Payload::ByteArray(x) => P::ByteArray(x.to_vec()),
+            Payload::String(x) => P::String(x.clone().into_owned()),
+            Payload::List(id, x) => P::List(*id, x.iter().map(Payload::to_owned).collect()),
+            Payload::Compound(x) => P::Compound(x.iter().map(Tag::to_owned).collect()),
+            Payload::IntArray(x) => P::IntArray(x.to_vec()),
+            Payload::LongArray(x) => P::LongArray(x.to_vec()),
+        }
+    }
+}
+
+impl Tag<'_> {
+    /// Copies into an owned tag.
+    pub fn to_owned(&self) -> tag::Tag {
+        tag::Tag::new(self.name.clone().into_owned(), self.payload.to_owned())
+    }
+}
+
+/// An int or long array left encoded in the input, decoded on access.
+#[derive(Clone, Copy)]
+pub struct Array<'a, T> {
+    bytes: &'a [u8],
+    len: usize,
+    encoding: Encoding,
+    element: PhantomData<T>,
+}
+
+/// Element type of an `Array`.
+pub trait Element: Copy {
+    /// Encoded size outside the network encoding.
+    const SIZE: usize;
+
+    fn parse(encoding: Encoding, input: &[u8]) -> IResult<'_, Self>;
+}
+
+impl Element for i32 {
+    const SIZE: usize = 4;
+
+    fn parse(encoding: Encoding, input: &[u8]) -> IResult<'_, Self> {
+        encoding.int(input)
+    }
+}
+
+impl Element for i64 {
+    const SIZE: usize = 8;
+
+    fn parse(encoding: Encoding, input: &[u8]) -> IResult<'_, Self> {
+        encoding.long(input)
+    }
+}
+
+impl<'a, T: Element> Array<'a, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The encoded elements.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Decodes element `i`. Network encoded arrays are VarInts, so this
+    /// walks the elements before it.
+    pub fn get(&self, i: usize) -> Option<T> {
+        match self.encoding {
+            _ if i >= self.len => None,
+            Encoding::Network => self.iter().nth(i),
+            _ => T::parse(self.encoding, &self.bytes[i * T::SIZE..]).ok().map(|(_, x)| x),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter { bytes: self.bytes, len: self.len, encoding: self.encoding, element: PhantomData }
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<T: Element + fmt::Debug> fmt::Debug for Array<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Arrays are equal when their elements are, whatever their encoding.
+impl<T: Element + PartialEq> PartialEq for Array<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, T: Element> IntoIterator for &Array<'a, T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T> {
+    bytes: &'a [u8],
+    len: usize,
+    encoding: Encoding,
+    element: PhantomData<T>,
+}
+
+impl<T: Element> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let (rest, x) = T::parse(self.encoding, self.bytes).ok()?;
+        self.bytes = rest;
+        self.len -= 1;
+        Some(x)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T: Element> ExactSizeIterator for Iter<'_, T> {}
+
+/// Parses the root tag at the start of `input` like `parser::parse`, without
+/// copying.
+pub fn parse(input: &[u8], opts: Options) -> Result<(Tag<'_>, &[u8]), Error> {
+    let result = match opts.nameless_root {
+        true => nameless_with(opts)(input).map(|(rest, payload)| (rest, Tag { name: Cow::Borrowed(""), payload })),
+        false => tag_with(opts)(input),
+    };
+    error::finish(input, result)
+}
+
+pub fn tag(input: &[u8]) -> IResult<'_, Tag<'_>> {
+    tag_with(Options::default())(input)
+}
+
+pub fn tag_with<'a>(opts: Options) -> impl Fn(&'a [u8]) -> IResult<'a, Tag<'a>> {
+    move |input| {
+        let (rest, (id, name)) = header_with(opts)(input)?;
+        let (rest, payload) = payload_with(opts, id)(rest)?;
+        Ok((rest, Tag { name, payload }))
+    }
+}
+
+/// The id and name in front of a payload.
+pub fn header_with<'a>(opts: Options) -> impl Fn(&'a [u8]) -> IResult<'a, (i8, Cow<'a, str>)> {
+    move |input| {
+        let (rest, id) = raw::byte(input)?;
+        if !(1..=12).contains(&id) {
+            return error::fail(input, Reason::UnknownTag(id));
+        }
+        let (rest, nlen) = opts.encoding.string_length(rest)?;
+        let (rest, name) = opts.encoding.string(opts.strings, nlen)(rest)?;
+        Ok((rest, (id, name)))
+    }
+}
+
+/// A root tag without a name; see `tag::nameless`.
+pub fn nameless_with<'a>(opts: Options) -> impl Fn(&'a [u8]) -> IResult<'a, Payload<'a>> {
+    move |input| {
+        let (rest, id) = raw::byte(input)?;
+        match id {
+            0 => Ok((rest, Payload::End)),
+            1..=12 => payload_with(opts, id)(rest),
+            _ => error::fail(input, Reason::UnknownTag(id)),
+        }
+    }
+}
+
+pub fn payload_with<'a>(opts: Options, id: i8) -> impl Fn(&'a [u8]) -> IResult<'a, Payload<'a>> {
+    move |input| payload_in(opts, Scope::root(opts, input), id)(input)
+}
+
+pub(super) fn payload_in<'a>(opts: Options, scope: Scope, id: i8) -> impl Fn(&'a [u8]) -> IResult<'a, Payload<'a>> {
+    let enc = opts.encoding;
+    move |input| {
+        let (rest, p) = match id {
+            1 => map(raw::byte, Payload::Byte)(input),
+            2 => map(|i| enc.short(i), Payload::Short)(input),
+            3 => map(|i| enc.int(i), Payload::Int)(input),
+            4 => map(|i| enc.long(i), Payload::Long)(input),
+            5 => map(|i| enc.float(i), Payload::Float)(input),
+            6 => map(|i| enc.double(i), Payload::Double)(input),
+            7 => {
+                let (rest, c) = scope.length(opts, input, 1)?;
+                map(take(c), Payload::ByteArray)(rest)
+            }
+            8 => {
+                let (rest, len) = enc.string_length(input)?;
+                scope.check(input, rest, len)?;
+                map(enc.string(opts.strings, len), Payload::String)(rest)
+            }
+            9 => list_in(opts, scope)(input),
+            10 => compound_in(opts, scope)(input),
+            11 => map(array_in(opts, scope), Payload::IntArray)(input),
+            12 => map(array_in(opts, scope), Payload::LongArray)(input),
+            _ => error::fail(input, Reason::UnknownTag(id)),
+        }?;
+        scope.reached(input, rest)?;
+        Ok((rest, p))
+    }
+}
+
+fn list_in<'a>(opts: Options, scope: Scope) -> impl Fn(&'a [u8]) -> IResult<'a, Payload<'a>> {
+    move |input| {
+        let (mut rest, (scope, id, c)) = scope.list(opts, input)?;
+        let mut items = Vec::with_capacity(c);
+        for i in 0..c {
+            let (r, p) = payload_in(opts, scope, id)(rest).map_err(|e| e.map(|e| e.within(Segment::Index(i))))?;
+            items.push(p);
+            rest = r;
+        }
+        Ok((rest, Payload::List(id, items)))
+    }
+}
+
+fn compound_in<'a>(opts: Options, scope: Scope) -> impl Fn(&'a [u8]) -> IResult<'a, Payload<'a>> {
+    move |mut input| {
+        let scope = scope.nested(opts, input)?;
+        let mut tags = Vec::new();
+        loop {
+            if let Ok((rest, ())) = tag::end(input) {
+                return Ok((rest, Payload::Compound(tags)));
+            }
+            let (rest, (id, name)) = header_with(opts)(input)?;
+            let (rest, payload) = payload_in(opts, scope, id)(rest)
+                .map_err(|e| e.map(|e| e.within(Segment::Name(name.to_string()))))?;
+            tags.push(Tag { name, payload });
+            input = rest;
+        }
+    }
+}
+
+/// Finds the extent of an array without decoding it, except for the VarInts
+/// of the network encoding which have to be walked.
+fn array_in<'a, T: Element>(opts: Options, scope: Scope) -> impl Fn(&'a [u8]) -> IResult<'a, Array<'a, T>> {
+    move |input| {
+        let enc = opts.encoding;
+        let (rest, len) = scope.length(opts, input, enc.width(T::SIZE))?;
+        let end = match enc {
+            Encoding::Network => {
+                let mut end = rest;
+                for _ in 0..len {
+                    end = T::parse(enc, end)?.0;
+                }
+                rest.len() - end.len()
+            }
+            _ => len * T::SIZE,
+        };
+        let (rest, bytes) = take(end)(rest)?;
+        Ok((rest, Array { bytes, len, encoding: enc, element: PhantomData }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::payload::Payload as Owned;
+    use crate::writer;
+
+    fn owned() -> tag::Tag {
+        tag::Tag::new("root", Owned::Compound(vec![
+            tag::Tag::new("b", Owned::Byte(-1)),
+            tag::Tag::new("s", Owned::String("plain".into())),
+            tag::Tag::new("nul", Owned::String("a\0b".into())),
+            tag::Tag::new("bytes", Owned::ByteArray(vec![1, 2, 3])),
+            tag::Tag::new("ints", Owned::IntArray(vec![1, -2, i32::MAX])),
+            tag::Tag::new("longs", Owned::LongArray(vec![i64::MIN, 5])),
+            tag::Tag::new("list", Owned::list(vec![Owned::Compound(vec![tag::Tag::new("x", Owned::Short(3))])])),
+        ]))
+    }
+
+    #[test]
+    fn borrowed() {
+        let mut data = Vec::new();
+        writer::tag(&mut data, &owned()).unwrap();
+        let (root, rest) = parse(&data, Options::default()).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(root.to_owned(), owned());
+
+        let Payload::Compound(tags) = &root.payload else { panic!("not a compound") };
+        assert!(matches!(tags[1].payload, Payload::String(Cow::Borrowed("plain"))));
+        assert!(matches!(tags[2].payload, Payload::String(Cow::Owned(_))));
+        assert!(matches!(tags[3].payload, Payload::ByteArray(&[1, 2, 3])));
+        let Payload::IntArray(ints) = &tags[4].payload else { panic!("not an int array") };
+        assert_eq!((ints.len(), ints.get(1), ints.get(3)), (3, Some(-2), None));
+        assert_eq!(ints.as_bytes()[..4], [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn encodings() {
+        for encoding in [Encoding::LittleEndian, Encoding::Network] {
+            let mut data = Vec::new();
+            writer::tag_with(&mut data, &owned(), encoding).unwrap();
+            let opts = Options { encoding, ..Options::default() };
+            let (root, _) = parse(&data, opts).unwrap();
+            assert_eq!(root.to_owned(), owned());
+            let Payload::Compound(tags) = &root.payload else { panic!("not a compound") };
+            let Payload::LongArray(longs) = &tags[5].payload else { panic!("not a long array") };
+            assert_eq!(longs.get(1), Some(5));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut data = Vec::new();
+        writer::tag(&mut data, &owned()).unwrap();
+        let e = parse(&data[..40], Options::default()).unwrap_err();
+        let owned = crate::parser::parse(&data[..40], Options::default()).unwrap_err();
+        assert_eq!((e.reason(), e.offset(), e.path()), (owned.reason(), owned.offset(), owned.path()));
+    }
+}
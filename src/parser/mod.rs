@@ -1,8 +1,88 @@
 pub mod raw;
 pub mod payload;
 pub mod tag;
+pub mod borrowed;
+pub mod skip;
 
+use crate::error::{self, Error, ParseError};
+use crate::parser::tag::Tag;
+
+/// Result of the nom parsers in this module.
+pub type IResult<'a, T> = nom::IResult<&'a [u8], T, ParseError<'a>>;
+
+/// Parses the root tag at the start of `input`, returning it with the input
+/// that follows. A nameless root is returned with an empty name.
+pub fn parse(input: &[u8], opts: Options) -> Result<(Tag, &[u8]), Error> {
+    let result = match opts.nameless_root {
+        true => tag::nameless_with(opts)(input).map(|(rest, payload)| (rest, Tag::new("", payload))),
+        false => tag::tag_with(opts)(input),
+    };
+    error::finish(input, result)
+}
+
+/// Settings shared by every parser in a tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    pub encoding: Encoding,
+    pub strings: Strings,
+    /// The root tag has no name and may be of any type, as sent by the Java
+    /// protocol since 1.20.2.
+    pub nameless_root: bool,
+    pub limits: Limits,
+}
+
+/// Bounds on untrusted input. Exceeding one fails the parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Deepest nesting of compounds and lists, the root compound being 1.
+    pub depth: usize,
+    /// Most bytes read for one root tag, after decompression.
+    pub bytes: usize,
+    /// Most elements in one array or list.
+    pub length: usize,
+}
+
+impl Limits {
+    pub const fn none() -> Self {
+        Self { depth: usize::MAX, bytes: usize::MAX, length: usize::MAX }
+    }
+}
+
+/// The limits vanilla applies when reading files.
+impl Default for Limits {
+    fn default() -> Self {
+        Self { depth: 512, bytes: 100 << 20, length: usize::MAX }
+    }
+}
+
+/// Byte layout of numbers, lengths and strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Java Edition: big-endian with Modified UTF-8 strings.
+    #[default]
+    BigEndian,
+    /// Bedrock Edition files: little-endian with UTF-8 strings.
+    LittleEndian,
+    /// Bedrock Edition network protocol: like `LittleEndian`, but ints, longs
+    /// and array lengths are ZigZag VarInts and string lengths are VarInts.
+    Network,
+}
+
+/// How strings that fail to decode are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strings {
+    /// Fail to parse.
+    #[default]
+    Strict,
+    /// Replace each invalid sequence with U+FFFD.
+    Lossy,
+}
+
+#[cfg(test)]
 mod tests {
+    use crate::parser::payload::Payload;
+    use crate::parser::tag::{self, Tag};
+
     static BYTE_TAG: &[u8] = &[
         0x01,                   //id byte
         0x00, 0x04,             //name length
@@ -25,10 +105,94 @@ mod tests {
         ];
 
     static LONG_TAG: &[u8] = &[
-        0x03,                   //id long
+        0x04,                   //id long
         0x00, 0x04,             //name length
         0x74, 0x65, 0x73, 0x74, //name: test
         0x01, 0x23, 0x45, 0x67,
         0x89, 0xAB, 0xCD, 0xEF, //value: 0x0123456789ABCDEF
         ];
+
+    static COMPOUND_TAG: &[u8] = &[
+        0x0A,                   //id compound
+        0x00, 0x04,             //name length
+        0x74, 0x65, 0x73, 0x74, //name: test
+        0x01,                   //child id byte
+        0x00, 0x01,             //child name length
+        0x62,                   //child name: b
+        0x01,                   //child value: 1
+        0x0A,                   //child id compound
+        0x00, 0x01,             //child name length
+        0x63,                   //child name: c
+        0x00,                   //child end
+        0x00,                   //end
+        ];
+
+    #[test]
+    fn byte_tag() {
+        assert_eq!(tag::tag(BYTE_TAG), Ok((&[] as &[u8], Tag::new("test", Payload::Byte(0x01)))));
+    }
+
+    #[test]
+    fn short_tag() {
+        assert_eq!(tag::tag(SHORT_TAG), Ok((&[] as &[u8], Tag::new("test", Payload::Short(0x0123)))));
+    }
+
+    #[test]
+    fn int_tag() {
+        assert_eq!(tag::tag(INT_TAG), Ok((&[] as &[u8], Tag::new("test", Payload::Int(0x01234567)))));
+    }
+
+    #[test]
+    fn long_tag() {
+        let p = Payload::Long(0x0123456789ABCDEF);
+        assert_eq!(tag::tag(LONG_TAG), Ok((&[] as &[u8], Tag::new("test", p))));
+    }
+
+    #[test]
+    fn nameless() {
+        let text: &[u8] = &[0x08, 0x00, 0x02, 0x68, 0x69];
+        assert_eq!(tag::nameless(text), Ok((&[] as &[u8], Payload::String("hi".into()))));
+        assert_eq!(tag::nameless(&[0x00]), Ok((&[] as &[u8], Payload::End)));
+        assert!(tag::nameless(&[0x0D]).is_err());
+
+        let compound = [&[0x0A][..], &COMPOUND_TAG[7..]].concat();
+        let (_, t) = tag::tag(COMPOUND_TAG).unwrap();
+        assert_eq!(tag::nameless(&compound), Ok((&[] as &[u8], t.payload)));
+    }
+
+    #[test]
+    fn parse() {
+        use crate::error::{Reason, Segment};
+        use crate::parser::{self, Options};
+
+        let (t, rest) = parser::parse(COMPOUND_TAG, Options::default()).unwrap();
+        assert_eq!((t.name.as_str(), rest), ("test", &[] as &[u8]));
+
+        let e = parser::parse(&COMPOUND_TAG[..11], Options::default()).unwrap_err();
+        assert_eq!((e.reason(), e.offset()), (&Reason::Truncated, 11));
+        assert_eq!(e.path().segments(), [Segment::Name("b".into())]);
+
+        let e = parser::parse(&[0x0A, 0x00, 0x00, 0x0F], Options::default()).unwrap_err();
+        assert_eq!((e.reason(), e.offset()), (&Reason::UnknownTag(15), 3));
+        assert!(e.path().is_empty());
+
+        // A huge claimed length fails on the missing data, not on allocation
+        let huge = |id: &[u8]| [&[0x0A, 0x00, 0x00][..], &id[..1], &[0x00, 0x01, 0x61], &id[1..], &[0x7F, 0xFF, 0xFF, 0xFF]].concat();
+        let opts = Options { limits: parser::Limits::none(), ..Options::default() };
+        for data in [huge(&[0x0C]), huge(&[0x0B]), huge(&[0x09, 0x0A])] {
+            let e = parser::parse(&data, opts).unwrap_err();
+            assert_eq!((e.reason(), e.offset()), (&Reason::Truncated, data.len()));
+            let e = parser::borrowed::parse(&data, opts).unwrap_err();
+            assert_eq!((e.reason(), e.offset()), (&Reason::Truncated, data.len()));
+        }
+    }
+
+    #[test]
+    fn compound_tag() {
+        let p = Payload::Compound(vec![
+            Tag::new("b", Payload::Byte(1)),
+            Tag::new("c", Payload::Compound(vec![])),
+        ]);
+        assert_eq!(tag::tag(COMPOUND_TAG), Ok((&[] as &[u8], Tag::new("test", p))));
+    }
 }
@@ -1,10 +1,8 @@
-use crate::parser::raw;
-use crate::parser::tag::{self, Tag};
+use crate::error::{self, Reason};
+use crate::parser::{borrowed, raw, IResult, Options};
+use crate::parser::tag::Tag;
 
-use nom::IResult;
-use nom::combinator::{self, map};
-use nom::number::complete::be_u32;
-use nom::multi::{count, many_till};
+use nom::combinator::map;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Payload {
@@ -17,8 +15,8 @@ pub enum Payload {
     Double(f64),
     ByteArray(Vec<u8>),
     String(String),
-    List(Vec<Payload>),
-    Compound,
+    List(i8, Vec<Payload>),
+    Compound(Vec<Tag>),
     IntArray(Vec<i32>),
     LongArray(Vec<i64>),
 }
@@ -33,8 +31,8 @@ impl Payload {
     pub fn double(&x: &f64) -> Self { Self::Double(x) }
     pub fn byte_array(x: Vec<u8>) -> Self { Self::ByteArray(x) }
     pub fn string(x: &str) -> Self { Self::String(String::from(x)) }
-    pub fn list(x: Vec<Payload>) -> Self { Self::List(x) }
-    pub fn compound(x: Vec<u8>) -> Self { Self::ByteArray(x) }
+    pub fn list(x: Vec<Payload>) -> Self { x.into() }
+    pub fn compound(x: Vec<Tag>) -> Self { Self::Compound(x) }
     pub fn int_array(x: Vec<i32>) -> Self { Self::IntArray(x) }
     pub fn long_array(x: Vec<i64>) -> Self { Self::LongArray(x) }
 
@@ -50,88 +48,172 @@ impl Payload {
             Double(_) => 6,
             ByteArray(_) => 7,
             String(_) => 8,
-            List(_) => 9,
-            Compound => 10,
+            List(..) => 9,
+            Compound(_) => 10,
             IntArray(_) => 11,
             LongArray(_) => 12,
         }
     }
 }
 
-pub fn payload<'a>(id: i8) -> impl Fn(&[u8]) -> IResult<&[u8], Payload> {
-    match id {
-        1 => byte,
-        2 => short,
-        3 => int,
-        4 => long,
-        5 => float,
-        6 => double,
-        7 => byte_array,
-        8 => string,
-        9 => list,
-        10 => todo!("compound"),
-        11 => int_array,
-        12 => long_array,
-        _ => fail,
+pub fn payload(id: i8) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    payload_with(Options::default(), id)
+}
+
+/// Parses a payload, enforcing `opts.limits` with this payload as the root.
+/// This is the borrowed parser's payload, copied.
+pub fn payload_with(opts: Options, id: i8) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    move |input: &[u8]| map(borrowed::payload_with(opts, id), |p| p.to_owned())(input)
+}
+
+/// Where in a tree a parser runs, for enforcing `Limits`.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Scope {
+    depth: usize,
+    /// Remaining input length below which the byte limit is exceeded.
+    floor: usize,
+}
+
+impl Scope {
+    pub(super) fn root(opts: Options, input: &[u8]) -> Self {
+        Scope { depth: 0, floor: input.len().saturating_sub(opts.limits.bytes) }
+    }
+
+    /// Bytes that may still be read from `input`.
+    pub(super) fn budget(self, input: &[u8]) -> usize {
+        input.len().saturating_sub(self.floor)
+    }
+
+    /// Enters a compound or list starting at `input`.
+    pub(super) fn nested(self, opts: Options, input: &[u8]) -> Result<Self, nom::Err<error::ParseError<'_>>> {
+        if self.depth >= opts.limits.depth {
+            return Err(nom::Err::Error(error::ParseError::new(input, Reason::DepthLimit)));
+        }
+        Ok(Scope { depth: self.depth + 1, ..self })
+    }
+
+    /// Reads the element count of an array or list whose elements take at
+    /// least `width` bytes each. A count the rest of the input cannot hold
+    /// is reported as truncated where the input ends, before anything is
+    /// allocated for it.
+    pub(super) fn length(self, opts: Options, input: &[u8], width: usize) -> IResult<'_, usize> {
+        let (rest, c) = opts.encoding.length(input)?;
+        if c > opts.limits.length {
+            return error::fail(input, Reason::LengthLimit(c));
+        }
+        let len = c.saturating_mul(width);
+        self.check(input, rest, len)?;
+        if len > rest.len() {
+            return error::fail(&rest[rest.len()..], Reason::Truncated);
+        }
+        Ok((rest, c))
+    }
+
+    /// Reads the element id and count of a list starting at `input`, and
+    /// enters it.
+    pub(super) fn list(self, opts: Options, input: &[u8]) -> IResult<'_, (Self, i8, usize)> {
+        let scope = self.nested(opts, input)?;
+        let (rest, id) = raw::byte(input)?;
+        // Only lists of End, which must be empty, have elements of no bytes
+        let (rest, c) = scope.length(opts, rest, (id != 0) as usize)?;
+        if !(0..=12).contains(&id) || (id == 0 && c > 0) {
+            return error::fail(input, Reason::UnknownTag(id));
+        }
+        Ok((rest, (scope, id, c)))
+    }
+
+    /// Fails at `input` if parsing up to `rest` read past the byte limit.
+    pub(super) fn reached<'a>(self, input: &'a [u8], rest: &[u8]) -> IResult<'a, ()> {
+        if rest.len() < self.floor {
+            return error::fail(input, Reason::SizeLimit);
+        }
+        Ok((input, ()))
+    }
+
+    /// Fails at `input` if reading `len` more bytes of `rest` would exceed
+    /// the byte limit. Running out of input is left to the caller.
+    pub(super) fn check<'a>(self, input: &'a [u8], rest: &[u8], len: usize) -> IResult<'a, ()> {
+        if len > self.budget(rest) && len <= rest.len() {
+            return error::fail(input, Reason::SizeLimit);
+        }
+        Ok((input, ()))
     }
 }
 
-pub fn fail(input: &[u8]) -> IResult<&[u8], Payload> {
-    combinator::fail(input)
+pub fn fail(input: &[u8]) -> IResult<'_, Payload> {
+    error::fail(input, Reason::Malformed)
 }
 
-pub fn byte(input: &[u8]) -> IResult<&[u8], Payload> {
+pub fn byte(input: &[u8]) -> IResult<'_, Payload> {
     map(raw::byte, into)(input)
 }
 
-pub fn short(input: &[u8]) -> IResult<&[u8], Payload> {
+pub fn short(input: &[u8]) -> IResult<'_, Payload> {
     map(raw::short, into)(input)
 }
 
-pub fn int(input: &[u8]) -> IResult<&[u8], Payload> {
+pub fn int(input: &[u8]) -> IResult<'_, Payload> {
     map(raw::int, into)(input)
 }
 
-pub fn long(input: &[u8]) -> IResult<&[u8], Payload> {
+pub fn long(input: &[u8]) -> IResult<'_, Payload> {
     map(raw::long, into)(input)
 }
 
-pub fn float(input: &[u8]) -> IResult<&[u8], Payload> {
+pub fn float(input: &[u8]) -> IResult<'_, Payload> {
     map(raw::float, into)(input)
 }
 
-pub fn double(input: &[u8]) -> IResult<&[u8], Payload> {
+pub fn double(input: &[u8]) -> IResult<'_, Payload> {
     map(raw::double, into)(input)
 }
 
-pub fn byte_array(input: &[u8]) -> IResult<&[u8], Payload> {
-    let (rest, c) = be_u32(input)?;
-    map(raw::byte_array(c as usize), into)(rest)
+pub fn byte_array(input: &[u8]) -> IResult<'_, Payload> {
+    byte_array_with(Options::default())(input)
+}
+
+pub fn byte_array_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    payload_with(opts, 7)
+}
+
+pub fn string(input: &[u8]) -> IResult<'_, Payload> {
+    string_with(Options::default())(input)
+}
+
+pub fn string_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    payload_with(opts, 8)
+}
+
+pub fn list(input: &[u8]) -> IResult<'_, Payload> {
+    list_with(Options::default())(input)
 }
 
-pub fn string<'a>(input: &'a [u8]) -> IResult<&'a [u8], Payload> {
-    let (rest, len) = raw::ushort(input)?;
-    map(raw::string(len as usize), into)(rest)
+pub fn list_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    payload_with(opts, 9)
 }
 
-pub fn list<'a>(input: &'a [u8]) -> IResult<&'a [u8], Payload> {
-    let (rest, id) = raw::byte(input)?;
-    let (rest, c) = be_u32(rest)?;
-    map(count(payload(id), c as usize), into)(rest)
+pub fn compound(input: &[u8]) -> IResult<'_, Payload> {
+    compound_with(Options::default())(input)
 }
 
-pub fn compound<'a>(input: &'a [u8]) -> IResult<&'a [u8], Vec<Tag>> {
-    map(many_till(tag::tag, tag::end), |(v,_)| v)(input)
+pub fn compound_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    payload_with(opts, 10)
 }
 
-pub fn int_array<'a>(input: &'a [u8]) -> IResult<&'a [u8], Payload> {
-    let (rest, c) = be_u32(input)?;
-    map(raw::int_array(c as usize), into)(rest)
+pub fn int_array(input: &[u8]) -> IResult<'_, Payload> {
+    int_array_with(Options::default())(input)
 }
 
-pub fn long_array<'a>(input: &'a [u8]) -> IResult<&'a [u8], Payload> {
-    let (rest, c) = be_u32(input)?;
-    map(raw::long_array(c as usize), into)(rest)
+pub fn int_array_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    payload_with(opts, 11)
+}
+
+pub fn long_array(input: &[u8]) -> IResult<'_, Payload> {
+    long_array_with(Options::default())(input)
+}
+
+pub fn long_array_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    payload_with(opts, 12)
 }
 
 pub struct TPayload<T: Into<Payload>> (T);
@@ -180,9 +262,15 @@ impl From<&[u8]> for Payload {
 
 impl From<Vec<Payload>> for Payload {
     fn from(i: Vec<Payload>) -> Self {
-        let id = i[0].id();
+        let id = i.first().map_or(0, Payload::id);
         i.iter().for_each(|p| if p.id() != id { panic!("Vector is not of uniform payload type") });
-        Self::List(i)
+        Self::List(id, i)
+    }
+}
+
+impl From<Vec<Tag>> for Payload {
+    fn from(i: Vec<Tag>) -> Self {
+        Self::Compound(i)
     }
 }
 
@@ -222,6 +310,7 @@ fn into<F, T: From<F>>(a: F) -> T {
 }
 
 #[cfg(test)]
+#[allow(clippy::approx_constant, clippy::excessive_precision)]
 mod tests {
     const NUMBER_IN: [u8; 24] = [
         0x40, 0x09, 0x21, 0xff,
@@ -278,6 +367,30 @@ mod tests {
         ];
     static LONG_ARRAY_OUT: &[i64] = &[0x0123456789abcdef, 0x0f2d4b6987a5c3e1];
 
+    static COMPOUND_IN: &[u8] = &[
+        0x03,                   // id int
+        0x00, 0x01, 0x69,       // name: i
+        0x01, 0x23, 0x45, 0x67, // value
+
+        0x0A,                   // id compound
+        0x00, 0x01, 0x63,       // name: c
+        0x08,                   // id string
+        0x00, 0x01, 0x73,       // name: s
+        0x00, 0x02, 0x68, 0x69, // value: hi
+        0x00,                   // end c
+
+        0x00,                   // end
+        ];
+
+    fn compound_out() -> Payload {
+        Payload::Compound(vec![
+            Tag::new("i", Payload::Int(0x01234567)),
+            Tag::new("c", Payload::Compound(vec![
+                Tag::new("s", Payload::String("hi".into())),
+            ])),
+        ])
+    }
+
     mod from {
         use crate::parser::payload::Payload;
 
@@ -335,34 +448,36 @@ mod tests {
         //#[ignore]
         fn from_vec() {
             let s = vec![Payload::Byte(1), Payload::Byte(2)];
-            assert_eq!(Into::<Payload>::into(s.clone()), Payload::List(s.clone()));
-            assert_eq!(Payload::from(s.clone()), Payload::List(s.clone()));
+            assert_eq!(Into::<Payload>::into(s.clone()), Payload::List(1, s.clone()));
+            assert_eq!(Payload::from(s.clone()), Payload::List(1, s.clone()));
         }
 
         #[test]
         #[should_panic(expected = "Vector is not of uniform payload type")]
         fn from_nonuniform_vec() {
             let s = vec![Payload::Byte(1), Payload::Short(2)];
-            assert_eq!(Into::<Payload>::into(s.clone()), Payload::List(s.clone()));
-            assert_eq!(Payload::from(s.clone()), Payload::List(s.clone()));
+            assert_eq!(Into::<Payload>::into(s.clone()), Payload::List(1, s.clone()));
+            assert_eq!(Payload::from(s.clone()), Payload::List(1, s.clone()));
         }
 
         #[test]
         fn from_int_slice() {
-            let v = unsafe { std::mem::transmute::<_, Vec<i32>>(super::NUMBER_IN.to_vec()) };
+            let v: Vec<i32> = super::NUMBER_IN.chunks(4).map(|c| i32::from_be_bytes(c.try_into().unwrap())).collect();
             assert_eq!(Into::<Payload>::into(&v[..]), Payload::IntArray(v.clone()));
             assert_eq!(Payload::from(&v[..]), Payload::IntArray(v.clone()));
         }
 
         #[test]
         fn from_long_slice() {
-            let v = unsafe { std::mem::transmute::<_, Vec<i64>>(super::NUMBER_IN.to_vec()) };
+            let v: Vec<i64> = super::NUMBER_IN.chunks(8).map(|c| i64::from_be_bytes(c.try_into().unwrap())).collect();
             assert_eq!(Into::<Payload>::into(&v[..]), Payload::LongArray(v.clone()));
             assert_eq!(Payload::from(&v[..]), Payload::LongArray(v.clone()));
         }
     }
 
     use crate::parser::payload::{self, Payload};
+    use crate::parser::tag::Tag;
+    use crate::parser::{Encoding, Options, Strings};
 
     #[test]
     fn byte() {
@@ -412,6 +527,41 @@ mod tests {
         assert_eq!(payload::string(STRING_IN), Ok((&STRING_IN[13..], p)));
     }
 
+    #[test]
+    fn string_lossy() {
+        let sin: &[u8] = &[0x00, 0x03, 0x61, 0xff, 0x62];
+        let lossy = Options { strings: Strings::Lossy, ..Options::default() };
+        assert!(payload::string(sin).is_err());
+        let p = Payload::String("a\u{FFFD}b".into());
+        assert_eq!(payload::string_with(lossy)(sin), Ok((&[] as &[u8], p)));
+    }
+
+    #[test]
+    fn little_endian() {
+        let le = Options { encoding: Encoding::LittleEndian, ..Options::default() };
+        let lin: &[u8] = &[
+            0x0A,                   // id compound
+            0x02, 0x00, 0x00, 0x00, // length
+            0x00,                   // empty compound
+            0x02, 0x01, 0x00, 0x73, // short "s"
+            0x23, 0x01,             // value
+            0x00,                   // end
+            0x01, 0x00, 0x00, 0x00, // int array length
+            0x01, 0x00, 0x00, 0x00, // 1
+            0x02, 0x00, 0xC3, 0xA9, // utf-8 string
+            0x00, 0x00,             // rest
+            ];
+        let p = Payload::List(10, vec![
+            Payload::Compound(vec![]),
+            Payload::Compound(vec![Tag::new("s", Payload::Short(0x0123))]),
+        ]);
+        assert_eq!(payload::list_with(le)(lin), Ok((&lin[13..], p)));
+        let p = Payload::IntArray(vec![1]);
+        assert_eq!(payload::payload_with(le, 11)(&lin[13..]), Ok((&lin[21..], p)));
+        let p = Payload::String("\u{e9}".into());
+        assert_eq!(payload::payload_with(le, 8)(&lin[21..]), Ok((&lin[25..], p)));
+    }
+
     #[test]
     fn list() {
         let mut lin: Vec<u8> = Vec::with_capacity(5+20*2);
@@ -420,33 +570,33 @@ mod tests {
 
         lin[0] = 1; // Byte
         lin.splice(5.., NUMBER_IN);
-        let p = Payload::List(BYTE_OUT.into_iter().map(Payload::byte).collect());
+        let p = Payload::list(BYTE_OUT.iter().map(Payload::byte).collect());
         assert_eq!(payload::list(&lin), Ok((&NUMBER_IN[2..], p)));
 
         lin[0] = 2; // Short
-        let p = Payload::List(SHORT_OUT.into_iter().map(Payload::short).collect());
+        let p = Payload::list(SHORT_OUT.iter().map(Payload::short).collect());
         assert_eq!(payload::list(&lin), Ok((&NUMBER_IN[4..], p)));
 
         lin[0] = 3; // Int
-        let p = Payload::List(INT_OUT.into_iter().map(Payload::int).collect());
+        let p = Payload::list(INT_OUT.iter().map(Payload::int).collect());
         assert_eq!(payload::list(&lin), Ok((&NUMBER_IN[8..], p)));
 
         lin[0] = 4; // Long
-        let p = Payload::List(LONG_OUT.into_iter().map(Payload::long).collect());
+        let p = Payload::list(LONG_OUT.iter().map(Payload::long).collect());
         assert_eq!(payload::list(&lin), Ok((&NUMBER_IN[16..], p)));
 
         lin[0] = 5; // Float
-        let p = Payload::List(FLOAT_OUT.into_iter().map(Payload::float).collect());
+        let p = Payload::list(FLOAT_OUT.iter().map(Payload::float).collect());
         assert_eq!(payload::list(&lin), Ok((&NUMBER_IN[8..], p)));
 
         lin[0] = 6; // Double
-        let p = Payload::List(DOUBLE_OUT.into_iter().map(Payload::double).collect());
+        let p = Payload::list(DOUBLE_OUT.iter().map(Payload::double).collect());
         assert_eq!(payload::list(&lin), Ok((&NUMBER_IN[16..], p)));
 
         lin[0] = 7; // Byte array
         lin.resize(5 + 7 * 2, 0);
         lin.splice(5.., BYTE_ARRAY_IN[..7].repeat(2));
-        let p = Payload::List(vec![
+        let p = Payload::list(vec![
             Payload::ByteArray(BYTE_ARRAY_OUT.to_vec()),
             Payload::ByteArray(BYTE_ARRAY_OUT.to_vec())
         ]);
@@ -455,18 +605,28 @@ mod tests {
         lin[0] = 8; // String
         lin.resize(5 + 13 * 2, 0);
         lin.splice(5.., STRING_IN[..13].repeat(2));
-        let p = Payload::List(vec![
+        let p = Payload::list(vec![
             Payload::String(String::from(STRING_OUT)),
             Payload::String(String::from(STRING_OUT))
         ]);
         assert_eq!(payload::list(&lin), Ok((&[] as &[u8], p)));
-        // lin[0] = 9; // List
-        // lin[0] = 10; // Compound
+
+        lin[0] = 9; // List
+        lin.resize(5 + 5 * 2, 0);
+        lin.splice(5.., [1, 0, 0, 0, 0].repeat(2));
+        let p = Payload::List(9, vec![Payload::List(1, vec![]), Payload::List(1, vec![])]);
+        assert_eq!(payload::list(&lin), Ok((&[] as &[u8], p)));
+
+        lin[0] = 10; // Compound
+        lin.resize(5 + COMPOUND_IN.len() * 2, 0);
+        lin.splice(5.., COMPOUND_IN.repeat(2));
+        let p = Payload::list(vec![compound_out(), compound_out()]);
+        assert_eq!(payload::list(&lin), Ok((&[] as &[u8], p)));
 
         lin[0] = 11; // Int array
         lin.resize(5 + 12 * 2, 0);
         lin.splice(5.., INT_ARRAY_IN[..12].repeat(2));
-        let p = Payload::List(vec![
+        let p = Payload::list(vec![
             Payload::IntArray(INT_ARRAY_OUT.to_vec()),
             Payload::IntArray(INT_ARRAY_OUT.to_vec())
         ]);
@@ -475,7 +635,7 @@ mod tests {
         lin[0] = 12; // Long array
         lin.resize(5 + 20 * 2, 0);
         lin.splice(5.., LONG_ARRAY_IN[..20].repeat(2));
-        let p = Payload::List(vec![
+        let p = Payload::list(vec![
             Payload::LongArray(LONG_ARRAY_OUT.to_vec()),
             Payload::LongArray(LONG_ARRAY_OUT.to_vec())
         ]);
@@ -484,9 +644,71 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
     fn compound() {
-        todo!("Compound payload test");
+        let mut cin = COMPOUND_IN.to_vec();
+        cin.extend([0x67, 0x89]); // rest
+        assert_eq!(payload::compound(&cin), Ok((&cin[COMPOUND_IN.len()..], compound_out())));
+
+        let empty: &[u8] = &[0x00];
+        assert_eq!(payload::compound(empty), Ok((&[] as &[u8], Payload::Compound(vec![]))));
+
+        let unterminated = &COMPOUND_IN[..COMPOUND_IN.len() - 1];
+        assert!(payload::compound(unterminated).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        use crate::error::{ParseError, Reason, Segment};
+
+        let err = |input, reason| Err(nom::Err::Error(ParseError::new(input, reason)));
+        assert_eq!(payload::payload(13)(&NUMBER_IN), err(&NUMBER_IN, Reason::UnknownTag(13)));
+        assert_eq!(payload::int_array(&INT_ARRAY_IN[..8]), err(&INT_ARRAY_IN[8..8], Reason::Truncated));
+        let negative: &[u8] = &[0xff, 0xff, 0xff, 0xfe];
+        assert_eq!(payload::byte_array(negative), err(negative, Reason::NegativeLength(-2)));
+        let end_list: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x01];
+        assert_eq!(payload::list(end_list), err(end_list, Reason::UnknownTag(0)));
+
+        // A list of compounds whose second element has a bad string
+        let mut lin = vec![0x0A, 0x00, 0x00, 0x00, 0x02];
+        lin.extend(COMPOUND_IN);
+        lin.extend(&COMPOUND_IN[..18]);
+        lin.extend([0xFF, 0x00, 0x00]);
+        let e = ParseError::new(&lin[lin.len() - 3..], Reason::InvalidString)
+            .within(Segment::Name("s".into()))
+            .within(Segment::Name("c".into()))
+            .within(Segment::Index(1));
+        assert_eq!(payload::list(&lin), Err(nom::Err::Error(e)));
+    }
+
+    #[test]
+    fn limits() {
+        use crate::error::{ParseError, Reason, Segment};
+        use crate::parser::Limits;
+
+        let with = |limits| Options { limits, ..Options::default() };
+        let err = |input, reason| Err(nom::Err::Error(ParseError::new(input, reason)));
+
+        // Lists nested three deep
+        let nested: &[u8] = &[0x09, 0, 0, 0, 1, 0x09, 0, 0, 0, 1, 0x01, 0, 0, 0, 0];
+        let two = with(Limits { depth: 2, ..Limits::default() });
+        let e = ParseError::new(&nested[10..], Reason::DepthLimit).within(Segment::Index(0)).within(Segment::Index(0));
+        assert_eq!(payload::list_with(two)(nested), Err(nom::Err::Error(e)));
+        let three = with(Limits { depth: 3, ..Limits::default() });
+        assert!(payload::list_with(three)(nested).is_ok());
+
+        let one = with(Limits { length: 1, ..Limits::default() });
+        assert_eq!(payload::int_array_with(one)(INT_ARRAY_IN), err(INT_ARRAY_IN, Reason::LengthLimit(2)));
+
+        let small = with(Limits { bytes: 11, ..Limits::default() });
+        assert_eq!(payload::int_array_with(small)(INT_ARRAY_IN), err(INT_ARRAY_IN, Reason::SizeLimit));
+        let exact = with(Limits { bytes: 12, ..Limits::default() });
+        assert!(payload::int_array_with(exact)(INT_ARRAY_IN).is_ok());
+
+        let tiny = with(Limits { bytes: 10, ..Limits::default() });
+        let e = ParseError::new(&COMPOUND_IN[16..], Reason::SizeLimit)
+            .within(Segment::Name("s".into()))
+            .within(Segment::Name("c".into()));
+        assert_eq!(payload::compound_with(tiny)(COMPOUND_IN), Err(nom::Err::Error(e)));
     }
 
     #[test]
@@ -504,7 +726,7 @@ mod tests {
     #[test]
     fn payload() {
         // Modified int array input, not worth testing every type
-        let LIST: &[u8] = &[
+        let list: &[u8] = &[
             0x03, //id
             0x00, 0x00, 0x00, 0x02, // array length
 
@@ -522,8 +744,8 @@ mod tests {
         assert_eq!(payload::double(&NUMBER_IN), payload::payload(6)(&NUMBER_IN));
         assert_eq!(payload::byte_array(BYTE_ARRAY_IN), payload::payload(7)(BYTE_ARRAY_IN));
         assert_eq!(payload::string(STRING_IN), payload::payload(8)(STRING_IN));
-        assert_eq!(payload::list(LIST), payload::payload(9)(LIST));
-        // todo!("Payload 10 (compound) test");
+        assert_eq!(payload::list(list), payload::payload(9)(list));
+        assert_eq!(payload::compound(COMPOUND_IN), payload::payload(10)(COMPOUND_IN));
         assert_eq!(payload::int_array(INT_ARRAY_IN), payload::payload(11)(INT_ARRAY_IN));
         assert_eq!(payload::long_array(LONG_ARRAY_IN), payload::payload(12)(LONG_ARRAY_IN));
     }
@@ -1,64 +1,211 @@
+use std::borrow::Cow;
 use std::str;
-use nom::IResult;
 use nom::number::complete::*;
 use nom::bytes::complete::take;
 use nom::multi::count;
 use nom::combinator::map;
-//use crate::parser::payload::{Payload, payload};
+use crate::error::{self, Reason};
+use crate::mutf8;
+use crate::parser::{Encoding, IResult, Strings};
 
-pub fn byte(input: &[u8]) -> IResult<&[u8], i8> {
+pub fn byte(input: &[u8]) -> IResult<'_, i8> {
     i8(input)
 }
 
-pub fn ubyte(input: &[u8]) -> IResult<&[u8], u8> {
+pub fn ubyte(input: &[u8]) -> IResult<'_, u8> {
     u8(input)
 }
 
-pub fn short(input: &[u8]) -> IResult<&[u8], i16> {
+pub fn short(input: &[u8]) -> IResult<'_, i16> {
     be_i16(input)
 }
 
-pub fn ushort(input: &[u8]) -> IResult<&[u8], u16> {
+pub fn ushort(input: &[u8]) -> IResult<'_, u16> {
     be_u16(input)
 }
 
-pub fn int(input: &[u8]) -> IResult<&[u8], i32> {
+pub fn int(input: &[u8]) -> IResult<'_, i32> {
     be_i32(input)
 }
 
-pub fn long(input: &[u8]) -> IResult<&[u8], i64> {
+pub fn long(input: &[u8]) -> IResult<'_, i64> {
     be_i64(input)
 }
 
-pub fn float(input: &[u8]) -> IResult<&[u8], f32> {
+pub fn float(input: &[u8]) -> IResult<'_, f32> {
     be_f32(input)
 }
 
-pub fn double(input: &[u8]) -> IResult<&[u8], f64> {
+pub fn double(input: &[u8]) -> IResult<'_, f64> {
     be_f64(input)
 }
 
-pub fn byte_array<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
+pub fn byte_array<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, &'a [u8]> {
     take(len)
 }
 
-pub fn int_array<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], Vec<i32>> {
+pub fn int_array<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Vec<i32>> {
     count(int, len)
 }
 
-pub fn long_array<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], Vec<i64>> {
+pub fn long_array<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Vec<i64>> {
     count(long, len)
 }
 
-pub fn string<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], &'a str> {
-    map(take(len), unsafe { |s| str::from_utf8_unchecked(s) })
+pub fn string<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Cow<'a, str>> {
+    move |input| {
+        let (rest, bytes) = take(len)(input)?;
+        match mutf8::decode(bytes) {
+            Ok(s) => Ok((rest, s)),
+            Err(e) => error::fail(&input[e.valid_up_to()..], Reason::InvalidString),
+        }
+    }
+}
+
+pub fn string_lossy<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Cow<'a, str>> {
+    map(take(len), mutf8::decode_lossy)
+}
+
+pub fn string_with<'a>(strings: Strings, len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Cow<'a, str>> {
+    move |input| match strings {
+        Strings::Strict => string(len)(input),
+        Strings::Lossy => string_lossy(len)(input),
+    }
+}
+
+impl Encoding {
+    pub fn short(self, input: &[u8]) -> IResult<'_, i16> {
+        match self {
+            Encoding::BigEndian => be_i16(input),
+            Encoding::LittleEndian | Encoding::Network => le_i16(input),
+        }
+    }
+
+    pub fn ushort(self, input: &[u8]) -> IResult<'_, u16> {
+        match self {
+            Encoding::BigEndian => be_u16(input),
+            Encoding::LittleEndian | Encoding::Network => le_u16(input),
+        }
+    }
+
+    pub fn int(self, input: &[u8]) -> IResult<'_, i32> {
+        match self {
+            Encoding::BigEndian => be_i32(input),
+            Encoding::LittleEndian => le_i32(input),
+            Encoding::Network => {
+                // Five bytes hold 35 bits, more than an int has
+                let (rest, x) = map(varint(5), zigzag)(input)?;
+                match i32::try_from(x) {
+                    Ok(x) => Ok((rest, x)),
+                    Err(_) => error::fail(input, Reason::Malformed),
+                }
+            }
+        }
+    }
+
+    pub fn long(self, input: &[u8]) -> IResult<'_, i64> {
+        match self {
+            Encoding::BigEndian => be_i64(input),
+            Encoding::LittleEndian => le_i64(input),
+            Encoding::Network => map(varint(10), zigzag)(input),
+        }
+    }
+
+    pub fn float(self, input: &[u8]) -> IResult<'_, f32> {
+        match self {
+            Encoding::BigEndian => be_f32(input),
+            Encoding::LittleEndian | Encoding::Network => le_f32(input),
+        }
+    }
+
+    pub fn double(self, input: &[u8]) -> IResult<'_, f64> {
+        match self {
+            Encoding::BigEndian => be_f64(input),
+            Encoding::LittleEndian | Encoding::Network => le_f64(input),
+        }
+    }
+
+    /// Number of elements in an array or list, which may not be negative.
+    pub fn length(self, input: &[u8]) -> IResult<'_, usize> {
+        let (rest, len) = self.int(input)?;
+        match usize::try_from(len) {
+            Ok(len) => Ok((rest, len)),
+            Err(_) => error::fail(input, Reason::NegativeLength(len)),
+        }
+    }
+
+    /// Number of bytes in a string.
+    pub fn string_length(self, input: &[u8]) -> IResult<'_, usize> {
+        match self {
+            Encoding::Network => {
+                let (rest, x) = varint(5)(input)?;
+                match u32::try_from(x) {
+                    Ok(x) => Ok((rest, x as usize)),
+                    Err(_) => error::fail(input, Reason::Malformed),
+                }
+            }
+            _ => map(|i| self.ushort(i), usize::from)(input),
+        }
+    }
+
+    /// Fewest bytes an int or long of `size` bytes is encoded in.
+    pub fn width(self, size: usize) -> usize {
+        match self {
+            Encoding::Network => 1,
+            _ => size,
+        }
+    }
+
+    pub fn int_array<'a>(self, len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Vec<i32>> {
+        count(move |i| self.int(i), len)
+    }
+
+    pub fn long_array<'a>(self, len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Vec<i64>> {
+        count(move |i| self.long(i), len)
+    }
+
+    /// Java strings are Modified UTF-8, Bedrock strings are plain UTF-8.
+    pub fn string<'a>(self, strings: Strings, len: usize) -> impl FnMut(&'a [u8]) -> IResult<'a, Cow<'a, str>> {
+        move |input| {
+            if self == Encoding::BigEndian {
+                return string_with(strings, len)(input);
+            }
+            let (rest, bytes) = take(len)(input)?;
+            match (str::from_utf8(bytes), strings) {
+                (Ok(s), _) => Ok((rest, Cow::Borrowed(s))),
+                (Err(_), Strings::Lossy) => Ok((rest, String::from_utf8_lossy(bytes))),
+                (Err(e), Strings::Strict) => error::fail(&input[e.valid_up_to()..], Reason::InvalidString),
+            }
+        }
+    }
+}
+
+/// Unsigned LEB128 VarInt of at most `max` bytes.
+pub fn varint<'a>(max: usize) -> impl Fn(&'a [u8]) -> IResult<'a, u64> {
+    move |input| {
+        let mut x = 0;
+        for (i, &b) in input.iter().take(max).enumerate() {
+            x |= ((b & 0x7F) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok((&input[i + 1..], x));
+            }
+        }
+        let reason = if input.len() < max { Reason::Truncated } else { Reason::VarIntTooLong };
+        error::fail(input, reason)
+    }
+}
+
+/// Decodes a ZigZag encoded signed integer.
+pub fn zigzag(x: u64) -> i64 {
+    (x >> 1) as i64 ^ -((x & 1) as i64)
 }
 
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests {
     use super::*;
 
-    const s: [u8; 24] = [0x40, 0x09, 0x21, 0xff,
+    const S: [u8; 24] = [0x40, 0x09, 0x21, 0xff,
                          0x2e, 0x48, 0xe8, 0xa7,
 
                          0x9a, 0x99, 0x99, 0x99,
@@ -69,59 +216,118 @@ mod tests {
 
     #[test]
     fn byte_test() {
-        assert_eq!(byte(&s[3..]), Ok((&s[4..], -0x01)))
+        assert_eq!(byte(&S[3..]), Ok((&S[4..], -0x01)))
     }
 
     #[test]
     fn ubyte_test() {
-        assert_eq!(ubyte(&s[3..]), Ok((&s[4..], 0xff)))
+        assert_eq!(ubyte(&S[3..]), Ok((&S[4..], 0xff)))
     }
 
     #[test]
     fn short_test() {
-        assert_eq!(short(&s[..]), Ok((&s[2..], 0x4009)))
+        assert_eq!(short(&S[..]), Ok((&S[2..], 0x4009)))
     }
 
     #[test]
     fn int_test() {
-        assert_eq!(int(&s[..]), Ok((&s[4..], 0x400921ff)))
+        assert_eq!(int(&S[..]), Ok((&S[4..], 0x400921ff)))
     }
 
     #[test]
     fn long_test() {
-        assert_eq!(long(&s[..]), Ok((&s[8..], 0x400921ff2e48e8a7)))
+        assert_eq!(long(&S[..]), Ok((&S[8..], 0x400921ff2e48e8a7)))
     }
 
     #[test]
     fn float_test() {
-        assert_eq!(float(&s[..]), Ok((&s[4..], 2.1427)));
+        assert_eq!(float(&S[..]), Ok((&S[4..], 2.1427)));
     }
 
     #[test]
     fn double_test() {
-        assert_eq!(double(&s[..]), Ok((&s[8..], 3.1416)));
+        assert_eq!(double(&S[..]), Ok((&S[8..], 3.1416)));
     }
 
     #[test]
     fn byte_array_test() {
-        assert_eq!(byte_array(8)(&s[..]), Ok((&s[8..], &s[..8])));
+        assert_eq!(byte_array(8)(&S[..]), Ok((&S[8..], &S[..8])));
     }
 
     #[test]
     fn int_array_test() {
         let t = vec![0x400921ff, 0x2e48e8a7, -0x65666667, -0x6666E4C0];
-        assert_eq!(int_array(4)(&s[..]), Ok((&s[16..], t)));
+        assert_eq!(int_array(4)(&S[..]), Ok((&S[16..], t)));
     }
 
     #[test]
     fn long_array_test() {
         let t = vec![0x400921ff2e48e8a7, -0x656666666666E4C0];
-        assert_eq!(long_array(2)(&s[..]), Ok((&s[16..], t)));
+        assert_eq!(long_array(2)(&S[..]), Ok((&S[16..], t)));
     }
 
     #[test]
     fn string_test() {
         let t = [0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0xff];
-        assert_eq!(string(11)(&t[..]), Ok((&t[11..], "hello world")));
+        assert_eq!(string(11)(&t[..]), Ok((&t[11..], "hello world".into())));
+    }
+
+    #[test]
+    fn little_endian_test() {
+        let le = Encoding::LittleEndian;
+        assert_eq!(le.short(&S[..]), Ok((&S[2..], 0x0940)));
+        assert_eq!(le.int(&S[..]), Ok((&S[4..], -0xdef6c0)));
+        assert_eq!(le.long(&S[8..]), Ok((&S[16..], 0x401b99999999999a)));
+        assert_eq!(le.double(&S[8..]), Ok((&S[16..], 6.9)));
+        assert_eq!(le.length(&S[12..]), Ok((&S[16..], 0x401b9999)));
+        let e = le.length(&S[..]).unwrap_err();
+        assert_eq!(e, nom::Err::Error(error::ParseError::new(&S[..], Reason::NegativeLength(-0xdef6c0))));
+        assert_eq!(le.int_array(2)(&S[..]), Ok((&S[8..], vec![-0xdef6c0, -0x5817b7d2])));
+    }
+
+    #[test]
+    fn varint_test() {
+        let t = [0xAC, 0x02, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F, 0x80];
+        assert_eq!(varint(5)(&t[..]), Ok((&t[2..], 300)));
+        assert_eq!(varint(5)(&t[3..]), Ok((&t[8..], u32::MAX as u64)));
+        assert!(varint(5)(&t[8..]).is_err());
+        assert!(varint(3)(&t[3..]).is_err());
+        assert_eq!([0, 1, 2, 3, 4294967294].map(zigzag), [0, -1, 1, -2, 2147483647]);
+    }
+
+    #[test]
+    fn network_test() {
+        let n = Encoding::Network;
+        let t = [0x03, 0xAC, 0x02, 0x01, 0x00, 0x04, 0x00];
+        assert_eq!(n.int(&t[..]), Ok((&t[1..], -2)));
+        assert_eq!(n.long(&t[1..]), Ok((&t[3..], 150)));
+        assert_eq!(n.short(&t[3..]), Ok((&t[5..], 1)));
+        assert_eq!(n.length(&t[5..]), Ok((&t[6..], 2)));
+        assert_eq!(n.string_length(&t[1..]), Ok((&t[3..], 300)));
+        assert_eq!(n.int_array(2)(&t[..]), Ok((&t[3..], vec![-2, 150])));
+
+        // 2^33 and 2^32 do not fit in an int or a string length
+        let wide = [0x80, 0x80, 0x80, 0x80, 0x20];
+        assert_eq!(n.int(&wide), Err(nom::Err::Error(error::ParseError::new(&wide[..], Reason::Malformed))));
+        let wide = [0x80, 0x80, 0x80, 0x80, 0x10];
+        assert_eq!(n.string_length(&wide), Err(nom::Err::Error(error::ParseError::new(&wide[..], Reason::Malformed))));
+    }
+
+    #[test]
+    fn utf8_string_test() {
+        let t = "\u{1F600}\0".as_bytes();
+        let le = Encoding::LittleEndian;
+        assert_eq!(le.string(Strings::Strict, 5)(t), Ok((&t[5..], "\u{1F600}\0".into())));
+        assert!(Encoding::BigEndian.string(Strings::Strict, 5)(t).is_err());
+        assert!(le.string(Strings::Strict, 3)(t).is_err());
+        assert_eq!(le.string(Strings::Lossy, 3)(t), Ok((&t[3..], "\u{FFFD}".into())));
+    }
+
+    #[test]
+    fn string_mutf8_test() {
+        let t = [0x61, 0xC0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80, 0xff];
+        assert_eq!(string(9)(&t[..]), Ok((&t[9..], "a\0\u{1F600}".into())));
+        assert!(string(10)(&t[..]).is_err());
+        assert_eq!(string_lossy(10)(&t[..]), Ok((&t[10..], "a\0\u{1F600}\u{FFFD}".into())));
     }
 }
\ No newline at end of file
@@ -0,0 +1,257 @@
+//! Skipping payloads by their length alone, and parsing only selected tags.
+
+use std::borrow::Cow;
+
+use nom::bytes::complete::take;
+
+use crate::error::{self, Error, Path, Reason, Segment};
+use crate::mutf8;
+use crate::parser::payload::{Payload, Scope};
+use crate::parser::{borrowed, raw, tag, Encoding, IResult, Options};
+
+pub fn skip(id: i8) -> impl Fn(&[u8]) -> IResult<'_, &[u8]> {
+    skip_with(Options::default(), id)
+}
+
+/// Skips a payload without decoding it, returning its bytes. Arrays and
+/// lists of numbers are skipped by count and width; lists and compounds of
+/// anything else by walking their headers. Strings are not validated.
+pub fn skip_with(opts: Options, id: i8) -> impl Fn(&[u8]) -> IResult<'_, &[u8]> {
+    move |input: &[u8]| skip_in(opts, Scope::root(opts, input), id)(input)
+}
+
+fn skip_in(opts: Options, scope: Scope, id: i8) -> impl Fn(&[u8]) -> IResult<'_, &[u8]> {
+    let enc = opts.encoding;
+    move |input: &[u8]| {
+        let rest = match (id, width(enc, id)) {
+            (_, Some(w)) => take(w)(input)?.0,
+            (3, None) => enc.int(input)?.0,
+            (4, None) => enc.long(input)?.0,
+            (7, _) => {
+                let (rest, c) = scope.length(opts, input, 1)?;
+                take(c)(rest)?.0
+            }
+            (8, _) => {
+                let (rest, len) = enc.string_length(input)?;
+                scope.check(input, rest, len)?;
+                take(len)(rest)?.0
+            }
+            (9, _) => list(opts, scope, input)?.0,
+            (10, _) => compound(opts, scope, input)?.0,
+            (11, _) => elements(opts, scope, 3, input)?.0,
+            (12, _) => elements(opts, scope, 4, input)?.0,
+            _ => return error::fail(input, Reason::UnknownTag(id)),
+        };
+        scope.reached(input, rest)?;
+        Ok((rest, &input[..input.len() - rest.len()]))
+    }
+}
+
+/// Encoded size of a number with this id, unless it is a VarInt.
+fn width(enc: Encoding, id: i8) -> Option<usize> {
+    match (id, enc) {
+        (1, _) => Some(1),
+        (2, _) => Some(2),
+        (3, Encoding::BigEndian | Encoding::LittleEndian) | (5, _) => Some(4),
+        (4, Encoding::BigEndian | Encoding::LittleEndian) | (6, _) => Some(8),
+        _ => None,
+    }
+}
+
+/// Skips a count followed by that many numbers with id `id`.
+fn elements(opts: Options, scope: Scope, id: i8, input: &[u8]) -> IResult<'_, ()> {
+    let w = width(opts.encoding, id);
+    let (mut rest, c) = scope.length(opts, input, w.unwrap_or(1))?;
+    match w {
+        Some(w) => rest = take(c.saturating_mul(w))(rest)?.0,
+        None => {
+            for _ in 0..c {
+                rest = skip_in(opts, scope, id)(rest)?.0;
+            }
+        }
+    }
+    Ok((rest, ()))
+}
+
+fn list(opts: Options, scope: Scope, input: &[u8]) -> IResult<'_, ()> {
+    let (rest, id) = raw::byte(input)?;
+    if width(opts.encoding, id).is_some() || matches!(id, 3 | 4) {
+        return elements(opts, scope.nested(opts, input)?, id, rest);
+    }
+    let (mut rest, (scope, id, c)) = scope.list(opts, input)?;
+    for i in 0..c {
+        rest = skip_in(opts, scope, id)(rest).map_err(|e| e.map(|e| e.within(Segment::Index(i))))?.0;
+    }
+    Ok((rest, ()))
+}
+
+fn compound(opts: Options, scope: Scope, mut input: &[u8]) -> IResult<'_, ()> {
+    let scope = scope.nested(opts, input)?;
+    loop {
+        if let Ok((rest, ())) = tag::end(input) {
+            return Ok((rest, ()));
+        }
+        let (rest, (id, name)) = header(opts, input)?;
+        input = skip_in(opts, scope, id)(rest).map_err(|e| e.map(|e| e.within(Segment::Name(lossy(opts, name).into_owned()))))?.0;
+    }
+}
+
+/// The id and undecoded name in front of a payload.
+fn header(opts: Options, input: &[u8]) -> IResult<'_, (i8, &[u8])> {
+    let (rest, id) = raw::byte(input)?;
+    if !(1..=12).contains(&id) {
+        return error::fail(input, Reason::UnknownTag(id));
+    }
+    let (rest, len) = opts.encoding.string_length(rest)?;
+    let (rest, name) = take(len)(rest)?;
+    Ok((rest, (id, name)))
+}
+
+fn lossy(opts: Options, name: &[u8]) -> Cow<'_, str> {
+    match opts.encoding {
+        Encoding::BigEndian => mutf8::decode_lossy(name),
+        _ => String::from_utf8_lossy(name),
+    }
+}
+
+/// Parses only the tags at `paths`, relative to the root as in `Error::path`,
+/// and skips everything else. Returns an entry for each path, `None` where
+/// the tag does not exist.
+pub fn select(input: &[u8], opts: Options, paths: &[Path]) -> Result<Vec<Option<Payload>>, Error> {
+    let mut out = vec![None; paths.len()];
+    let wanted: Vec<_> = paths.iter().map(Path::segments).enumerate().collect();
+    let result = match opts.nameless_root {
+        true => raw::byte(input).and_then(|(rest, id)| match id {
+            0 => Ok((rest, ())),
+            _ => select_in(opts, Scope::root(opts, rest), id, &wanted, &mut out, rest),
+        }),
+        false => header(opts, input).and_then(|(rest, (id, _))| {
+            select_in(opts, Scope::root(opts, rest), id, &wanted, &mut out, rest)
+        }),
+    };
+    error::finish(input, result).map(|_| out)
+}
+
+type Wanted<'p> = [(usize, &'p [Segment])];
+
+fn select_in<'a>(
+    opts: Options,
+    scope: Scope,
+    id: i8,
+    wanted: &Wanted,
+    out: &mut [Option<Payload>],
+    input: &'a [u8],
+) -> IResult<'a, ()> {
+    if wanted.is_empty() {
+        return Ok((skip_in(opts, scope, id)(input)?.0, ()));
+    }
+    if wanted.iter().any(|(_, path)| path.is_empty()) {
+        // Everything below is needed anyway
+        let (rest, p) = borrowed::payload_in(opts, scope, id)(input)?;
+        let p = p.to_owned();
+        for &(i, path) in wanted {
+            out[i] = get(&p, path).cloned();
+        }
+        return Ok((rest, ()));
+    }
+    let child = |segment: &Segment| -> Vec<_> {
+        wanted.iter().filter(|(_, path)| &path[0] == segment).map(|&(i, path)| (i, &path[1..])).collect()
+    };
+    match id {
+        9 => {
+            let (mut rest, (scope, id, c)) = scope.list(opts, input)?;
+            for i in 0..c {
+                let segment = Segment::Index(i);
+                rest = select_in(opts, scope, id, &child(&segment), out, rest)
+                    .map_err(|e| e.map(|e| e.within(segment)))?
+                    .0;
+            }
+            Ok((rest, ()))
+        }
+        10 => {
+            let scope = scope.nested(opts, input)?;
+            let mut input = input;
+            loop {
+                if let Ok((rest, ())) = tag::end(input) {
+                    return Ok((rest, ()));
+                }
+                let (rest, (id, name)) = header(opts, input)?;
+                let segment = Segment::Name(lossy(opts, name).into_owned());
+                input = select_in(opts, scope, id, &child(&segment), out, rest)
+                    .map_err(|e| e.map(|e| e.within(segment)))?
+                    .0;
+            }
+        }
+        _ => Ok((skip_in(opts, scope, id)(input)?.0, ())),
+    }
+}
+
+/// The payload at `path` below `p`.
+fn get<'p>(p: &'p Payload, path: &[Segment]) -> Option<&'p Payload> {
+    match (path.first(), p) {
+        (None, _) => Some(p),
+        (Some(Segment::Name(name)), Payload::Compound(tags)) => {
+            get(&tags.iter().find(|t| &t.name == name)?.payload, &path[1..])
+        }
+        (Some(Segment::Index(i)), Payload::List(_, items)) => get(items.get(*i)?, &path[1..]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tag::Tag;
+    use crate::writer;
+
+    fn chunk() -> Tag {
+        Tag::new("", Payload::Compound(vec![
+            Tag::new("Level", Payload::Compound(vec![
+                Tag::new("xPos", Payload::Int(3)),
+                Tag::new("Sections", Payload::list(vec![
+                    Payload::Compound(vec![
+                        Tag::new("Y", Payload::Byte(0)),
+                        Tag::new("BlockStates", Payload::LongArray(vec![7; 256])),
+                    ]),
+                    Payload::Compound(vec![Tag::new("Y", Payload::Byte(1))]),
+                ])),
+                Tag::new("Heights", Payload::List(3, vec![Payload::Int(1), Payload::Int(2)])),
+                Tag::new("zPos", Payload::Int(-4)),
+            ])),
+            Tag::new("DataVersion", Payload::Int(2975)),
+        ]))
+    }
+
+    #[test]
+    fn skip() {
+        for encoding in [Encoding::BigEndian, Encoding::LittleEndian, Encoding::Network] {
+            let opts = Options { encoding, ..Options::default() };
+            let mut data = Vec::new();
+            writer::tag_with(&mut data, &chunk(), encoding).unwrap();
+            data.push(0xAA);
+            let (body, _) = header(opts, &data).unwrap();
+            let (rest, skipped) = skip_with(opts, 10)(body).unwrap();
+            assert_eq!((rest, skipped), (&[0xAA][..], &body[..body.len() - 1]));
+        }
+
+        let truncated: &[u8] = &[0x00, 0x00, 0x00, 0x02, 0x01];
+        assert!(super::skip(12)(truncated).is_err());
+        let bad: &[u8] = &[0x0D, 0x00, 0x00];
+        assert_eq!(super::skip(10)(bad), Err(nom::Err::Error(error::ParseError::new(bad, Reason::UnknownTag(13)))));
+    }
+
+    #[test]
+    fn select() {
+        let mut data = Vec::new();
+        writer::tag(&mut data, &chunk()).unwrap();
+        let paths = ["Level.xPos", "Level.zPos", "Level.Sections[1].Y", "Level.Sections[0]", "Level.Sections[0].Y", "Nope"]
+            .map(Path::from);
+        let found = super::select(&data, Options::default(), &paths).unwrap();
+        assert_eq!(found[..3], [Some(Payload::Int(3)), Some(Payload::Int(-4)), Some(Payload::Byte(1))]);
+        assert!(matches!(&found[3], Some(Payload::Compound(tags)) if tags.len() == 2));
+        assert_eq!(found[4..], [Some(Payload::Byte(0)), None]);
+
+        let e = super::select(&data[..60], Options::default(), &paths[..1]).unwrap_err();
+        assert_eq!((e.reason(), e.path().to_string()), (&Reason::Truncated, "Level.Sections[0].BlockStates".into()));
+    }
+}
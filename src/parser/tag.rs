@@ -1,23 +1,44 @@
-use crate::parser::payload::{self, Payload};
-use crate::parser::raw;
+use crate::parser::payload::Payload;
+use crate::parser::{borrowed, IResult, Options};
 
-use nom::IResult;
-use nom::combinator::{map, verify, value};
+use nom::combinator::{map, value};
 use nom::bytes::complete as bytes;
 
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Tag {
     pub name: String,
     pub payload: Payload
 }
 
-pub fn end(input: &[u8]) -> IResult<&[u8], ()> {
+impl Tag {
+    pub fn new(name: impl Into<String>, payload: Payload) -> Self {
+        Tag { name: name.into(), payload }
+    }
+}
+
+pub fn end(input: &[u8]) -> IResult<'_, ()> {
     value((), bytes::tag([0u8]))(input)
 }
 
-pub fn tag(input: &[u8]) -> IResult<&[u8], Tag> {
-    let (rest, id) = verify(raw::byte, |&i| i > 0 && i <= 12)(input)?;
-    let (rest, nlen) = raw::ushort(rest)?;
-    let (rest, name) = map(raw::string(nlen as usize), String::from)(rest)?;
-    let (rest, payload) = payload::payload(id)(rest)?;
-    Ok((rest, Tag { name, payload }))
-}
\ No newline at end of file
+pub fn tag(input: &[u8]) -> IResult<'_, Tag> {
+    tag_with(Options::default())(input)
+}
+
+pub fn tag_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Tag> {
+    move |input: &[u8]| map(borrowed::tag_with(opts), |t| t.to_owned())(input)
+}
+
+/// The id and name in front of a payload.
+pub fn header_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, (i8, String)> {
+    move |input: &[u8]| map(borrowed::header_with(opts), |(id, name)| (id, name.into_owned()))(input)
+}
+
+/// A root tag as sent by the Java protocol since 1.20.2: an id followed
+/// directly by the payload. An id of 0 stands for no data at all.
+pub fn nameless(input: &[u8]) -> IResult<'_, Payload> {
+    nameless_with(Options::default())(input)
+}
+
+pub fn nameless_with(opts: Options) -> impl Fn(&[u8]) -> IResult<'_, Payload> {
+    move |input: &[u8]| map(borrowed::nameless_with(opts), |p| p.to_owned())(input)
+}
@@ -0,0 +1,583 @@
+//! Anvil region files (`r.X.Z.mca`), which hold the chunks of a 32×32 area.
+//!
+//! A region starts with two tables of 1024 big-endian entries, indexed by
+//! `x + z * 32` in local chunk coordinates: where each chunk starts and how
+//! many 4 KiB sectors it takes, then when it was last saved. A chunk is its
+//! length, a compression type and the compressed root tag.
+//!
+//! MCRegion files (`r.X.Z.mcr`), which came before Anvil, are laid out the
+//! same way and read with the same type; only their chunks differ.
+//!
+//! Chunks too large for 255 sectors are stored in a file of their own next to
+//! the region, `c.X.Z.mcc` in world chunk coordinates, and marked by the high
+//! bit of the compression type.
+
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::compression::Compression;
+use crate::error::{self, Error, Reason};
+use crate::file;
+use crate::parser::tag::Tag;
+use crate::parser::Options;
+use crate::writer;
+
+/// Regions are allocated in sectors of this many bytes.
+pub const SECTOR: usize = 4096;
+const CHUNKS: usize = 1024;
+/// Set in the compression type of a chunk stored in its own file.
+const EXTERNAL: u8 = 128;
+
+/// Where a chunk is stored, in sectors from the start of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub offset: u32,
+    pub sectors: u8,
+}
+
+impl Location {
+    fn from_entry(entry: u32) -> Option<Self> {
+        match entry {
+            0 => None,
+            _ => Some(Self { offset: entry >> 8, sectors: entry as u8 }),
+        }
+    }
+
+    fn entry(location: Option<Self>) -> u32 {
+        location.map_or(0, |l| l.offset << 8 | l.sectors as u32)
+    }
+
+    fn end(self) -> u32 {
+        self.offset + self.sectors as u32
+    }
+}
+
+/// A chunk read from a region, at local coordinates `x` and `z`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub x: i32,
+    pub z: i32,
+    /// Seconds since the Unix epoch when the chunk was last saved.
+    pub timestamp: u32,
+    pub root: Tag,
+}
+
+/// Reads chunks from a region file, and writes them if `R` allows. Chunk
+/// coordinates are taken modulo 32, so world chunk coordinates can be used
+/// as well as local ones.
+pub struct Region<R> {
+    inner: R,
+    opts: Options,
+    locations: Vec<Option<Location>>,
+    timestamps: Vec<u32>,
+    /// The file is empty, so the header has to be written before anything
+    /// else.
+    blank: bool,
+    /// The directory external chunk files are in, and the region's
+    /// coordinates.
+    external: Option<(PathBuf, i32, i32)>,
+}
+
+impl Region<BufReader<fs::File>> {
+    /// Opens a region for reading. If the file is named `r.X.Z.mca`, chunks
+    /// stored in files of their own can be read too.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        Ok(Self::new(BufReader::new(fs::File::open(path)?))?.located(path))
+    }
+}
+
+impl Region<fs::File> {
+    /// Opens a region for reading and writing, creating an empty one if the
+    /// file does not exist.
+    pub fn open_rw<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        Ok(Self::new(file)?.located(path))
+    }
+}
+
+impl<R: Read + Seek> Region<R> {
+    pub fn new(inner: R) -> Result<Self, Error> {
+        Self::new_with(inner, Options::default())
+    }
+
+    /// Reads the header of the region `inner` holds. An empty file is a
+    /// region without chunks. `opts` applies to each chunk's root tag.
+    pub fn new_with(mut inner: R, opts: Options) -> Result<Self, Error> {
+        let mut header = vec![0; 2 * SECTOR];
+        inner.seek(SeekFrom::Start(0)).map_err(|e| io(e, 0))?;
+        let n = read_full(&mut inner, &mut header).map_err(|e| io(e, 0))?;
+        if n != 0 && n != header.len() {
+            return Err(Error::new(Reason::Truncated, n, error::Path::default()));
+        }
+        let entries: Vec<u32> = header.chunks_exact(4).map(|e| u32::from_be_bytes([e[0], e[1], e[2], e[3]])).collect();
+        let locations = entries[..CHUNKS].iter().map(|&e| Location::from_entry(e)).collect();
+        let timestamps = entries[CHUNKS..].to_vec();
+        Ok(Self { inner, opts, locations, timestamps, blank: n == 0, external: None })
+    }
+
+    /// Sets where chunks stored in files of their own are found: `dir`, for
+    /// the region at `x`, `z` in region coordinates.
+    pub fn with_external<P: Into<PathBuf>>(mut self, dir: P, x: i32, z: i32) -> Self {
+        self.external = Some((dir.into(), x, z));
+        self
+    }
+
+    /// Looks for external chunks next to `path`, if it is named like a region.
+    fn located(self, path: &Path) -> Self {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        match name.split('.').collect::<Vec<_>>()[..] {
+            ["r", x, z, _] => match (x.parse(), z.parse()) {
+                (Ok(x), Ok(z)) => self.with_external(path.parent().unwrap_or(Path::new("")), x, z),
+                _ => self,
+            },
+            _ => self,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    pub fn location(&self, x: i32, z: i32) -> Option<Location> {
+        self.locations[index(x, z)]
+    }
+
+    /// When the chunk was last saved, or 0 if it never was.
+    pub fn timestamp(&self, x: i32, z: i32) -> u32 {
+        self.timestamps[index(x, z)]
+    }
+
+    /// Reads and parses the chunk at `x`, `z`, or `None` if it is absent.
+    pub fn chunk(&mut self, x: i32, z: i32) -> Result<Option<Tag>, Error> {
+        self.read(index(x, z))
+    }
+
+    /// Every chunk present, in the order of the location table. An error
+    /// reading one chunk does not stop the others from being read.
+    pub fn chunks(&mut self) -> Chunks<'_, R> {
+        Chunks { region: self, next: 0 }
+    }
+
+    /// Writes every chunk to `w` as a new region, one after the other with
+    /// nothing in between, keeping their compression and timestamps. Chunks
+    /// in files of their own stay there and are only marked as such in the
+    /// new region, so it needs to be placed where those files are, or they
+    /// need to be copied next to it.
+    pub fn repack<W: Write>(&mut self, mut w: W) -> Result<(), Error> {
+        let mut header = vec![0; 2 * SECTOR];
+        let mut chunks = Vec::new();
+        let mut next = 2;
+        for i in 0..CHUNKS {
+            let Some((_, kind, data)) = self.raw(i)? else { continue };
+            let sector = sector(kind, &data)?;
+            let location = Location { offset: next, sectors: (sector.len() / SECTOR) as u8 };
+            header[i * 4..i * 4 + 4].copy_from_slice(&Location::entry(Some(location)).to_be_bytes());
+            header[SECTOR + i * 4..SECTOR + i * 4 + 4].copy_from_slice(&self.timestamps[i].to_be_bytes());
+            next = location.end();
+            chunks.push(sector);
+        }
+        w.write_all(&header).map_err(|e| io(e, 0))?;
+        for (n, sector) in chunks.iter().enumerate() {
+            w.write_all(sector).map_err(|e| io(e, (n + 2) * SECTOR))?;
+        }
+        w.flush().map_err(|e| io(e, 0))
+    }
+
+    fn read(&mut self, i: usize) -> Result<Option<Tag>, Error> {
+        let Some((at, kind, mut data)) = self.raw(i)? else { return Ok(None) };
+        let compression = match kind & !EXTERNAL {
+            1 => Compression::Gzip,
+            2 => Compression::Zlib,
+            3 => Compression::None,
+            4 => Compression::Lz4,
+            _ => return Err(Error::new(Reason::UnknownCompression(kind), at - 1, error::Path::default())),
+        };
+        if kind & EXTERNAL != 0 {
+            let path = self.external_path(i).ok_or_else(|| {
+                let e = io::Error::new(io::ErrorKind::NotFound, "no directory for external chunk files");
+                Error::io(e, at - 1, error::Path::default())
+            })?;
+            data = fs::read(path).map_err(|e| Error::io(e, at - 1, error::Path::default()))?;
+        }
+        let limit = self.opts.limits.bytes;
+        let data = compression.decompress_limited(&data, limit).map_err(|e| io(e, at))?;
+        if data.len() > limit {
+            return Err(Error::new(Reason::SizeLimit, limit, error::Path::default()));
+        }
+        file::root(&data, self.opts).map(Some)
+    }
+
+    /// The compression type and compressed data of chunk `i`, along with the
+    /// offset of the data in the file.
+    fn raw(&mut self, i: usize) -> Result<Option<(usize, u8, Vec<u8>)>, Error> {
+        let Some(location) = self.locations[i] else { return Ok(None) };
+        let at = location.offset as usize * SECTOR;
+        if location.offset < 2 {
+            return Err(Error::new(Reason::Malformed, i * 4, error::Path::default()));
+        }
+        self.inner.seek(SeekFrom::Start(at as u64)).map_err(|e| io(e, at))?;
+        let mut header = [0; 5];
+        self.inner.read_exact(&mut header).map_err(|e| io(e, at))?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if len == 0 || len + 4 > location.sectors as usize * SECTOR {
+            return Err(Error::new(Reason::Malformed, at, error::Path::default()));
+        }
+        let mut data = vec![0; len - 1];
+        self.inner.read_exact(&mut data).map_err(|e| io(e, at + 5))?;
+        Ok(Some((at + 5, header[4], data)))
+    }
+
+    /// The file chunk `i` is stored in if it is too large for the region.
+    fn external_path(&self, i: usize) -> Option<PathBuf> {
+        let (dir, x, z) = self.external.as_ref()?;
+        let (cx, cz) = (x * 32 + (i % 32) as i32, z * 32 + (i / 32) as i32);
+        Some(dir.join(format!("c.{}.{}.mcc", cx, cz)))
+    }
+}
+
+impl<R: Read + Write + Seek> Region<R> {
+    /// Compresses and stores `root` as the chunk at `x`, `z`, stamped with
+    /// the current time. The chunk goes in the first free sectors large
+    /// enough, never over its old copy, which is only freed once the header
+    /// points to the new one; a write that fails midway leaves the old chunk
+    /// readable. A chunk too large for the region goes in a file of its own,
+    /// if the region knows where, written beside it first and then renamed
+    /// over the old one so that it is never left half written.
+    pub fn write_chunk(&mut self, x: i32, z: i32, root: &Tag, compression: Compression) -> Result<(), Error> {
+        let mut data = Vec::new();
+        writer::tag_with(&mut data, root, self.opts.encoding)?;
+        let data = compression.compress(&data)?;
+        let kind = match compression {
+            Compression::Gzip => 1,
+            Compression::Zlib => 2,
+            Compression::None => 3,
+            Compression::Lz4 => 4,
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32);
+        let i = index(x, z);
+        match self.external_path(i) {
+            Some(path) if data.len() + 5 > u8::MAX as usize * SECTOR => {
+                let tmp = path.with_extension("mcc.tmp");
+                fs::write(&tmp, &data)?;
+                fs::rename(&tmp, &path)?;
+                self.store(i, kind | EXTERNAL, &[], now)
+            }
+            _ => {
+                self.store(i, kind, &data, now)?;
+                self.remove_external(i)
+            }
+        }
+    }
+
+    /// Removes the chunk at `x`, `z`, freeing its sectors for other chunks.
+    pub fn delete_chunk(&mut self, x: i32, z: i32) -> Result<(), Error> {
+        self.set(index(x, z), None, 0)?;
+        self.remove_external(index(x, z))
+    }
+
+    /// Deletes the file chunk `i` was stored in when it was too large, if any.
+    fn remove_external(&self, i: usize) -> Result<(), Error> {
+        match self.external_path(i).map(fs::remove_file) {
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn store(&mut self, i: usize, kind: u8, data: &[u8], timestamp: u32) -> Result<(), Error> {
+        let sector = sector(kind, data)?;
+        let sectors = (sector.len() / SECTOR) as u8;
+        let offset = self.allocate(sectors);
+        let at = offset as usize * SECTOR;
+        self.init()?;
+        self.inner.seek(SeekFrom::Start(at as u64)).map_err(|e| io(e, at))?;
+        self.inner.write_all(&sector).map_err(|e| io(e, at))?;
+        self.set(i, Some(Location { offset, sectors }), timestamp)
+    }
+
+    /// The first run of `sectors` free sectors. Those of every chunk count
+    /// as taken, including the one being replaced.
+    fn allocate(&self, sectors: u8) -> u32 {
+        let taken = self.locations.iter().filter_map(|l| *l);
+        let end = taken.clone().map(Location::end).max().unwrap_or(2).max(2);
+        let mut used = vec![false; end as usize];
+        used[..2].fill(true);
+        for l in taken {
+            used[l.offset as usize..l.end() as usize].fill(true);
+        }
+        let mut start = 2;
+        for s in 2.. {
+            if used.get(s as usize).copied().unwrap_or(false) {
+                start = s + 1;
+            } else if s + 1 - start == sectors as u32 {
+                break;
+            }
+        }
+        start
+    }
+
+    /// Updates the header entries of chunk `i`.
+    fn set(&mut self, i: usize, location: Option<Location>, timestamp: u32) -> Result<(), Error> {
+        self.init()?;
+        for (at, value) in [(i * 4, Location::entry(location)), (SECTOR + i * 4, timestamp)] {
+            self.inner.seek(SeekFrom::Start(at as u64)).map_err(|e| io(e, at))?;
+            self.inner.write_all(&value.to_be_bytes()).map_err(|e| io(e, at))?;
+        }
+        self.inner.flush().map_err(|e| io(e, 0))?;
+        self.locations[i] = location;
+        self.timestamps[i] = timestamp;
+        Ok(())
+    }
+
+    /// Writes an empty header to a blank file.
+    fn init(&mut self) -> Result<(), Error> {
+        if self.blank {
+            self.inner.seek(SeekFrom::Start(0)).map_err(|e| io(e, 0))?;
+            self.inner.write_all(&[0; 2 * SECTOR]).map_err(|e| io(e, 0))?;
+            self.blank = false;
+        }
+        Ok(())
+    }
+}
+
+pub struct Chunks<'r, R> {
+    region: &'r mut Region<R>,
+    next: usize,
+}
+
+impl<R: Read + Seek> Iterator for Chunks<'_, R> {
+    type Item = Result<Chunk, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < CHUNKS {
+            let i = self.next;
+            self.next += 1;
+            if self.region.locations[i].is_none() {
+                continue;
+            }
+            let (x, z) = ((i % 32) as i32, (i / 32) as i32);
+            let timestamp = self.region.timestamps[i];
+            let chunk = self.region.read(i).transpose()?;
+            return Some(chunk.map(|root| Chunk { x, z, timestamp, root }));
+        }
+        None
+    }
+}
+
+/// A chunk as stored: length, compression type and data, padded to whole
+/// sectors.
+fn sector(kind: u8, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let sectors = (data.len() + 5).div_ceil(SECTOR);
+    if sectors > u8::MAX as usize {
+        let e = io::Error::new(io::ErrorKind::InvalidInput, "chunk does not fit in 255 sectors");
+        return Err(Error::io(e, 0, error::Path::default()));
+    }
+    let mut out = Vec::with_capacity(sectors * SECTOR);
+    out.extend(((data.len() + 1) as u32).to_be_bytes());
+    out.push(kind);
+    out.extend(data);
+    out.resize(sectors * SECTOR, 0);
+    Ok(out)
+}
+
+fn index(x: i32, z: i32) -> usize {
+    ((x & 31) + (z & 31) * 32) as usize
+}
+
+/// Reads until `buf` is full or the input ends, returning the bytes read.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// An I/O error at `offset`, reporting a short file as truncated.
+fn io(e: io::Error, offset: usize) -> Error {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::new(Reason::Truncated, offset, error::Path::default()),
+        _ => Error::io(e, offset, error::Path::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::parser::payload::Payload;
+    use crate::writer;
+
+    fn root(x: i32, z: i32) -> Tag {
+        Tag::new("", Payload::Compound(vec![Tag::new("xPos", Payload::Int(x)), Tag::new("zPos", Payload::Int(z))]))
+    }
+
+    /// A region holding `chunks`, each written one sector after the other.
+    fn region(chunks: &[(i32, i32, u8, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![0; 2 * SECTOR];
+        for (n, (x, z, kind, data)) in chunks.iter().enumerate() {
+            let i = index(*x, *z);
+            let offset = 2 + n as u32;
+            out[i * 4..i * 4 + 4].copy_from_slice(&(offset << 8 | 1).to_be_bytes());
+            out[SECTOR + i * 4..SECTOR + i * 4 + 4].copy_from_slice(&(1000 + n as u32).to_be_bytes());
+            let mut sector = ((data.len() + 1) as u32).to_be_bytes().to_vec();
+            sector.push(*kind);
+            sector.extend(data);
+            sector.resize(SECTOR, 0);
+            out.extend(sector);
+        }
+        out
+    }
+
+    fn compressed(tag: &Tag, compression: Compression) -> Vec<u8> {
+        let mut data = Vec::new();
+        writer::tag(&mut data, tag).unwrap();
+        compression.compress(&data).unwrap().into_owned()
+    }
+
+    #[test]
+    fn chunks() {
+        let data = region(&[
+            (0, 0, 2, compressed(&root(0, 0), Compression::Zlib)),
+            (31, 1, 1, compressed(&root(31, 1), Compression::Gzip)),
+            (5, 0, 3, compressed(&root(5, 0), Compression::None)),
+        ]);
+        let mut region = Region::new(Cursor::new(data)).unwrap();
+        assert_eq!(region.location(31, 1), Some(Location { offset: 3, sectors: 1 }));
+        assert_eq!((region.timestamp(-1, 33), region.timestamp(1, 1)), (1001, 0));
+        assert_eq!(region.chunk(5, 0).unwrap(), Some(root(5, 0)));
+        assert_eq!(region.chunk(-27, 32).unwrap(), Some(root(5, 0)));
+        assert_eq!(region.chunk(1, 1).unwrap(), None);
+
+        let chunks: Vec<_> = region.chunks().collect::<Result<_, _>>().unwrap();
+        let coords: Vec<_> = chunks.iter().map(|c| (c.x, c.z, c.timestamp)).collect();
+        assert_eq!(coords, [(0, 0, 1000), (5, 0, 1002), (31, 1, 1001)]);
+        assert_eq!(chunks[2].root, root(31, 1));
+
+        assert_eq!(Region::new(Cursor::new(Vec::new())).unwrap().chunks().count(), 0);
+    }
+
+    fn big(x: i32) -> Tag {
+        let mut tag = root(x, 0);
+        let Payload::Compound(tags) = &mut tag.payload else { unreachable!() };
+        tags.push(Tag::new("Data", Payload::LongArray(vec![x as i64; 700])));
+        tag
+    }
+
+    #[test]
+    fn write() {
+        let mut region = Region::new(Cursor::new(Vec::new())).unwrap();
+        region.write_chunk(0, 0, &root(0, 0), Compression::Zlib).unwrap();
+        region.write_chunk(1, 0, &big(1), Compression::None).unwrap();
+        region.write_chunk(2, 0, &root(2, 0), Compression::Gzip).unwrap();
+        let location = |r: &Region<_>, x| r.location(x, 0).map(|l| (l.offset, l.sectors));
+        assert_eq!([0, 1, 2].map(|x| location(&region, x)), [Some((2, 1)), Some((3, 2)), Some((5, 1))]);
+        assert!(region.timestamp(2, 0) > 0);
+
+        // Growing moves the chunk to the end, and the gap it leaves is reused
+        region.write_chunk(0, 0, &big(0), Compression::None).unwrap();
+        assert_eq!(location(&region, 0), Some((6, 2)));
+        region.write_chunk(3, 0, &root(3, 0), Compression::Zlib).unwrap();
+        assert_eq!(location(&region, 3), Some((2, 1)));
+        region.delete_chunk(1, 0).unwrap();
+        region.write_chunk(4, 0, &big(4), Compression::None).unwrap();
+        assert_eq!(location(&region, 4), Some((3, 2)));
+        // A chunk is never written over its old copy, even when it would
+        // fit, and the old sectors are free once it has moved
+        region.write_chunk(4, 0, &root(4, 0), Compression::None).unwrap();
+        assert_eq!(location(&region, 4), Some((8, 1)));
+        region.write_chunk(4, 0, &root(4, 0), Compression::None).unwrap();
+        assert_eq!(location(&region, 4), Some((3, 1)));
+
+        let data = region.into_inner().into_inner();
+        assert_eq!(data.len() % SECTOR, 0);
+        let mut region = Region::new(Cursor::new(data)).unwrap();
+        let expected = [(0, big(0)), (2, root(2, 0)), (3, root(3, 0)), (4, root(4, 0))];
+        let chunks: Vec<_> = region.chunks().map(|c| c.map(|c| (c.x, c.root))).collect::<Result<_, _>>().unwrap();
+        assert_eq!(chunks, expected);
+
+        let mut packed = Vec::new();
+        region.repack(&mut packed).unwrap();
+        assert_eq!(packed.len(), 7 * SECTOR);
+        let mut packed = Region::new(Cursor::new(packed)).unwrap();
+        assert_eq!([0, 2, 3, 4].map(|x| location(&packed, x)), [Some((2, 2)), Some((4, 1)), Some((5, 1)), Some((6, 1))]);
+        assert_eq!(packed.timestamp(3, 0), region.timestamp(3, 0));
+        let chunks: Vec<_> = packed.chunks().map(|c| c.map(|c| (c.x, c.root))).collect::<Result<_, _>>().unwrap();
+        assert_eq!(chunks, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn external() {
+        let dir = std::env::temp_dir().join(format!("nbt-region-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("r.-1.2.mca");
+        let huge = Tag::new("", Payload::Compound(vec![Tag::new("Data", Payload::LongArray((0..140_000).collect()))]));
+
+        let mut region = Region::open_rw(&path).unwrap();
+        region.write_chunk(1, 0, &root(1, 0), Compression::Lz4).unwrap();
+        region.write_chunk(0, 1, &huge, Compression::None).unwrap();
+        let mcc = dir.join("c.-32.65.mcc");
+        assert!(mcc.exists());
+        assert_eq!(region.location(0, 1).map(|l| l.sectors), Some(1));
+        drop(region);
+
+        // Replacing a chunk that is already external leaves no temporary file
+        let huger = Tag::new("", Payload::Compound(vec![Tag::new("Data", Payload::LongArray((1..140_001).collect()))]));
+        let mut region = Region::open_rw(&path).unwrap();
+        region.write_chunk(0, 1, &huger, Compression::None).unwrap();
+        region.write_chunk(0, 1, &huge, Compression::None).unwrap();
+        assert!(!dir.join("c.-32.65.mcc.tmp").exists());
+        drop(region);
+
+        let mut region = Region::open(&path).unwrap();
+        assert_eq!(region.chunk(1, 0).unwrap(), Some(root(1, 0)));
+        assert_eq!(region.chunk(0, 1).unwrap(), Some(huge.clone()));
+        // A repacked region still points to the chunk's file
+        let mut packed = Vec::new();
+        region.repack(&mut packed).unwrap();
+        let mut packed = Region::new(Cursor::new(packed)).unwrap().with_external(&dir, -1, 2);
+        assert_eq!(packed.chunk(0, 1).unwrap(), Some(huge.clone()));
+        // Without knowing where the region is, the chunk cannot be found
+        let inner = fs::read(&path).unwrap();
+        let e = Region::new(Cursor::new(inner)).unwrap().chunk(0, 1).unwrap_err();
+        assert_eq!(e.reason(), &Reason::Io);
+
+        let mut region = Region::open_rw(&path).unwrap();
+        region.write_chunk(0, 1, &root(0, 1), Compression::Lz4).unwrap();
+        assert!(!mcc.exists());
+        assert_eq!(region.chunk(0, 1).unwrap(), Some(root(0, 1)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let good = compressed(&root(0, 0), Compression::Zlib);
+        let data = region(&[(0, 0, 9, good.clone()), (1, 0, 2, good.clone()), (2, 0, 2, good[..10].to_vec())]);
+        let mut region = Region::new(Cursor::new(data.clone())).unwrap();
+        let e = region.chunk(0, 0).unwrap_err();
+        assert_eq!((e.reason(), e.offset()), (&Reason::UnknownCompression(9), 2 * SECTOR + 4));
+        let results: Vec<_> = region.chunks().map(|c| c.map(|c| c.x)).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].as_ref().unwrap(), &1);
+        assert_eq!(results[2].as_ref().unwrap_err().reason(), &Reason::Truncated);
+
+        let mut long = data.clone();
+        long[2 * SECTOR..2 * SECTOR + 4].copy_from_slice(&5000u32.to_be_bytes());
+        assert_eq!(Region::new(Cursor::new(long)).unwrap().chunk(0, 0).unwrap_err().reason(), &Reason::Malformed);
+
+        let short = &data[..3 * SECTOR + 2];
+        assert_eq!(Region::new(Cursor::new(short)).unwrap().chunk(1, 0).unwrap_err().reason(), &Reason::Truncated);
+        let e = Region::new(Cursor::new(&data[..100])).err().unwrap();
+        assert_eq!((e.reason(), e.offset()), (&Reason::Truncated, 100));
+    }
+}
@@ -0,0 +1,528 @@
+//! Serializing Rust types to NBT with serde.
+//!
+//! Structs and maps become compounds, sequences and tuples lists, and a
+//! `bool` a byte. `None` and unit fields are left out of compounds. Enums are
+//! written the way `de` reads them: a unit variant as a string, others as a
+//! compound with a single tag naming the variant. Sequences are written as
+//! lists, so their elements must all have the same type; use the wrappers in
+//! `array` to write an array instead. NBT has no unsigned numbers, so
+//! those are stored as the bits of the signed type of the same width.
+
+use std::fmt::Display;
+use std::io::Write;
+
+use serde::ser::{self, Serialize};
+
+use crate::array::{BYTE_ARRAY, INT_ARRAY, LONG_ARRAY};
+use crate::error::{Error, Segment};
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+use crate::parser::Encoding;
+use crate::writer;
+
+/// Serializes `value` as an uncompressed root tag with an empty name.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    to_bytes_with(value, Encoding::default())
+}
+
+pub fn to_bytes_with<T: Serialize + ?Sized>(value: &T, enc: Encoding) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    to_writer(&mut out, value, enc)?;
+    Ok(out)
+}
+
+/// Serializes `value` into `w`. The whole tree is built in memory with
+/// `to_payload` first and then written, so this takes as much memory as
+/// doing those two steps by hand.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(w: &mut W, value: &T, enc: Encoding) -> Result<(), Error> {
+    let root = Tag::new("", to_payload(value)?);
+    Ok(writer::tag_with(w, &root, enc)?)
+}
+
+/// `Payload::End` stands for `None` and unit values.
+pub fn to_payload<T: Serialize + ?Sized>(value: &T) -> Result<Payload, Error> {
+    value.serialize(Serializer)
+}
+
+/// Builds a `Payload` from any serializable value.
+pub struct Serializer;
+
+fn custom(msg: impl Display) -> Error {
+    <Error as ser::Error>::custom(msg)
+}
+
+/// The array `name` stands for, from what its contents serialized to.
+fn array(name: &'static str, p: Payload) -> Result<Payload, Error> {
+    let items = match (name, p) {
+        (BYTE_ARRAY, p @ Payload::ByteArray(_))
+        | (INT_ARRAY, p @ Payload::IntArray(_))
+        | (LONG_ARRAY, p @ Payload::LongArray(_)) => return Ok(p),
+        (_, Payload::List(_, items)) => items,
+        (_, other) => return Err(custom(format!("expected a sequence for {}, found tag id {}", name, other.id()))),
+    };
+    let out = match name {
+        BYTE_ARRAY => elements(&items, |p| match *p { Payload::Byte(x) => Some(x as u8), _ => None }).map(Payload::ByteArray),
+        INT_ARRAY => elements(&items, |p| match *p { Payload::Int(x) => Some(x), _ => None }).map(Payload::IntArray),
+        _ => elements(&items, |p| match *p { Payload::Long(x) => Some(x), _ => None }).map(Payload::LongArray),
+    };
+    out.ok_or_else(|| custom(format!("elements of {} have the wrong type", name)))
+}
+
+fn elements<T>(items: &[Payload], f: impl Fn(&Payload) -> Option<T>) -> Option<Vec<T>> {
+    items.iter().map(f).collect()
+}
+
+fn variant(name: &str, payload: Payload) -> Payload {
+    Payload::Compound(vec![Tag::new(name, payload)])
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Payload;
+    type Error = Error;
+    type SerializeSeq = List;
+    type SerializeTuple = List;
+    type SerializeTupleStruct = List;
+    type SerializeTupleVariant = Variant<List>;
+    type SerializeMap = Compound;
+    type SerializeStruct = Compound;
+    type SerializeStructVariant = Variant<Compound>;
+
+    fn serialize_bool(self, v: bool) -> Result<Payload, Error> {
+        Ok(Payload::Byte(v as i8))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Payload, Error> {
+        Ok(Payload::Byte(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Payload, Error> {
+        Ok(Payload::Short(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Payload, Error> {
+        Ok(Payload::Int(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Payload, Error> {
+        Ok(Payload::Long(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Payload, Error> {
+        Ok(Payload::Byte(v as i8))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Payload, Error> {
+        Ok(Payload::Short(v as i16))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Payload, Error> {
+        Ok(Payload::Int(v as i32))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Payload, Error> {
+        Ok(Payload::Long(v as i64))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Payload, Error> {
+        Ok(Payload::Float(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Payload, Error> {
+        Ok(Payload::Double(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Payload, Error> {
+        Ok(Payload::String(v.into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Payload, Error> {
+        Ok(Payload::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Payload, Error> {
+        Ok(Payload::ByteArray(v.into()))
+    }
+
+    fn serialize_none(self) -> Result<Payload, Error> {
+        Ok(Payload::End)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Payload, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Payload, Error> {
+        Ok(Payload::End)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Payload, Error> {
+        Ok(Payload::End)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Payload, Error> {
+        Ok(Payload::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<Payload, Error> {
+        let p = value.serialize(self)?;
+        match name {
+            BYTE_ARRAY | INT_ARRAY | LONG_ARRAY => array(name, p),
+            _ => Ok(p),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Payload, Error> {
+        let p = value.serialize(self).map_err(|e| e.within(Segment::Name(variant.into())))?;
+        if p == Payload::End {
+            return Err(custom(format!("variant {} holds no value, which a compound cannot store", variant)));
+        }
+        Ok(self::variant(variant, p))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<List, Error> {
+        Ok(List { items: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<List, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<List, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<List>, Error> {
+        Ok(Variant { name: variant, inner: self.serialize_seq(Some(len))? })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Compound, Error> {
+        Ok(Compound { tags: Vec::with_capacity(len.unwrap_or(0)), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Compound, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Variant<Compound>, Error> {
+        Ok(Variant { name: variant, inner: self.serialize_map(Some(len))? })
+    }
+}
+
+pub struct List {
+    items: Vec<Payload>,
+}
+
+impl ser::SerializeSeq for List {
+    type Ok = Payload;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let i = self.items.len();
+        let p = value.serialize(Serializer).map_err(|e| e.within(Segment::Index(i)))?;
+        match (self.items.first(), &p) {
+            (_, Payload::End) => Err(custom("a list cannot hold a missing value").within(Segment::Index(i))),
+            (Some(first), _) if first.id() != p.id() => Err(custom(format!(
+                "list elements must have the same type, found tag ids {} and {}",
+                first.id(),
+                p.id()
+            ))
+            .within(Segment::Index(i))),
+            _ => {
+                self.items.push(p);
+                Ok(())
+            }
+        }
+    }
+
+    fn end(self) -> Result<Payload, Error> {
+        Ok(self.items.into())
+    }
+}
+
+impl ser::SerializeTuple for List {
+    type Ok = Payload;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Payload, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for List {
+    type Ok = Payload;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Payload, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct Compound {
+    tags: Vec<Tag>,
+    /// The key whose value comes next.
+    key: Option<String>,
+}
+
+impl Compound {
+    fn insert(&mut self, name: String, p: Payload) {
+        if !matches!(p, Payload::End) {
+            self.tags.push(Tag::new(name, p));
+        }
+    }
+}
+
+impl ser::SerializeMap for Compound {
+    type Ok = Payload;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(Serializer)? {
+            Payload::String(name) => {
+                self.key = Some(name);
+                Ok(())
+            }
+            _ => Err(custom("compound keys must be strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let name = self.key.take().ok_or_else(|| custom("value serialized before key"))?;
+        match value.serialize(Serializer) {
+            Ok(p) => self.insert(name, p),
+            Err(e) => return Err(e.within(Segment::Name(name))),
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Payload, Error> {
+        Ok(Payload::Compound(self.tags))
+    }
+}
+
+impl ser::SerializeStruct for Compound {
+    type Ok = Payload;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let p = value.serialize(Serializer).map_err(|e| e.within(Segment::Name(key.into())))?;
+        self.insert(key.into(), p);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Payload, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// A tuple or struct variant, written as a compound holding its contents
+/// under the variant's name.
+pub struct Variant<T> {
+    name: &'static str,
+    inner: T,
+}
+
+impl ser::SerializeTupleVariant for Variant<List> {
+    type Ok = Payload;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let name = self.name;
+        ser::SerializeSeq::serialize_element(&mut self.inner, value).map_err(|e| e.within(Segment::Name(name.into())))
+    }
+
+    fn end(self) -> Result<Payload, Error> {
+        let p = ser::SerializeSeq::end(self.inner)?;
+        Ok(variant(self.name, p))
+    }
+}
+
+impl ser::SerializeStructVariant for Variant<Compound> {
+    type Ok = Payload;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let name = self.name;
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value).map_err(|e| e.within(Segment::Name(name.into())))
+    }
+
+    fn end(self) -> Result<Payload, Error> {
+        let p = ser::SerializeMap::end(self.inner)?;
+        Ok(variant(self.name, p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::array::{self, ByteArray, IntArray, LongArray};
+    use crate::de;
+    use crate::error::Reason;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Structure {
+        data_version: i32,
+        size: Vec<i32>,
+        #[serde(with = "array::int_array")]
+        origin: Vec<i32>,
+        blocks: Vec<Block>,
+        author: Option<String>,
+        entities: BTreeMap<String, Shape>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Block {
+        state: u8,
+        pos: IntArray,
+        light: ByteArray,
+        heights: LongArray,
+        powered: bool,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Shape {
+        Point,
+        Line(f32, f32),
+        Box { min: i16, max: i16 },
+    }
+
+    fn structure() -> Structure {
+        Structure {
+            data_version: 3465,
+            size: vec![1, 2, 3],
+            origin: vec![-1, 0, 1],
+            blocks: vec![Block {
+                state: 7,
+                pos: IntArray(vec![0, 64, 0]),
+                light: ByteArray(vec![0, 255]),
+                heights: LongArray(vec![]),
+                powered: true,
+            }],
+            author: None,
+            entities: BTreeMap::from([
+                ("a".into(), Shape::Point),
+                ("b".into(), Shape::Line(0.5, 1.0)),
+                ("c".into(), Shape::Box { min: -2, max: 2 }),
+            ]),
+        }
+    }
+
+    #[test]
+    fn payloads() {
+        let p = to_payload(&structure()).unwrap();
+        let Payload::Compound(tags) = &p else { panic!("{:?}", p) };
+        let names: Vec<_> = tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["DataVersion", "Size", "Origin", "Blocks", "Entities"]);
+        assert_eq!(tags[1].payload, Payload::List(3, vec![Payload::Int(1), Payload::Int(2), Payload::Int(3)]));
+        assert_eq!(tags[2].payload, Payload::IntArray(vec![-1, 0, 1]));
+        assert_eq!(tags[3].payload, Payload::list(vec![Payload::Compound(vec![
+            Tag::new("state", Payload::Byte(7)),
+            Tag::new("pos", Payload::IntArray(vec![0, 64, 0])),
+            Tag::new("light", Payload::ByteArray(vec![0, 255])),
+            Tag::new("heights", Payload::LongArray(vec![])),
+            Tag::new("powered", Payload::Byte(1)),
+        ])]));
+        assert_eq!(tags[4].payload, Payload::Compound(vec![
+            Tag::new("a", Payload::String("Point".into())),
+            Tag::new("b", Payload::Compound(vec![
+                Tag::new("Line", Payload::list(vec![Payload::Float(0.5), Payload::Float(1.0)])),
+            ])),
+            Tag::new("c", Payload::Compound(vec![Tag::new("Box", Payload::Compound(vec![
+                Tag::new("min", Payload::Short(-2)),
+                Tag::new("max", Payload::Short(2)),
+            ]))])),
+        ]));
+        assert_eq!(de::from_payload::<Structure>(p).unwrap(), structure());
+    }
+
+    #[test]
+    fn bytes() {
+        for enc in [Encoding::BigEndian, Encoding::LittleEndian, Encoding::Network] {
+            let data = to_bytes_with(&structure(), enc).unwrap();
+            let mut expected = Vec::new();
+            writer::tag_with(&mut expected, &Tag::new("", to_payload(&structure()).unwrap()), enc).unwrap();
+            assert_eq!(data, expected);
+        }
+        let data = to_bytes(&structure()).unwrap();
+        assert_eq!(de::from_bytes::<Structure>(&data).unwrap(), structure());
+    }
+
+    #[test]
+    fn unsigned() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Max {
+            a: u8,
+            b: u16,
+            c: u32,
+            d: u64,
+        }
+
+        assert_eq!(to_payload(&200u8).unwrap(), Payload::Byte(-56));
+        let max = Max { a: u8::MAX, b: u16::MAX, c: u32::MAX, d: u64::MAX };
+        let p = to_payload(&max).unwrap();
+        assert_eq!(p, Payload::Compound(vec![
+            Tag::new("a", Payload::Byte(-1)),
+            Tag::new("b", Payload::Short(-1)),
+            Tag::new("c", Payload::Int(-1)),
+            Tag::new("d", Payload::Long(-1)),
+        ]));
+        assert_eq!(de::from_payload::<Max>(p).unwrap(), max);
+        assert_eq!(de::from_bytes::<Max>(&to_bytes(&max).unwrap()).unwrap(), max);
+    }
+
+    #[test]
+    fn errors() {
+        #[derive(Serialize)]
+        #[serde(untagged)]
+        enum Mixed {
+            Int(i32),
+            Text(&'static str),
+        }
+
+        let mixed = BTreeMap::from([("Palette", vec![Mixed::Int(1), Mixed::Text("stone")])]);
+        let e = to_payload(&mixed).unwrap_err();
+        assert!(matches!(e.reason(), Reason::Custom(_)));
+        assert_eq!(e.to_string(), "list elements must have the same type, found tag ids 3 and 8 in Palette[1]");
+
+        assert!(to_payload(&vec![None, Some(1)]).is_err());
+        assert!(to_payload(&BTreeMap::from([(1, 2)])).is_err());
+        assert!(to_bytes(&None::<i32>).is_err());
+
+        #[derive(Serialize)]
+        enum Holder {
+            Empty(Option<i32>),
+        }
+        let e = to_payload(&Holder::Empty(None)).unwrap_err();
+        assert_eq!(e.to_string(), "variant Empty holds no value, which a compound cannot store");
+    }
+}
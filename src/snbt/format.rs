@@ -0,0 +1,266 @@
+//! Writing payloads as SNBT that `parse` reads back unchanged.
+
+use std::fmt::{self, Write};
+
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+
+/// How to lay out SNBT. The default is vanilla's compact form.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Format {
+    /// Spaces per nesting level, or `None` for everything on one line.
+    pub indent: Option<usize>,
+    /// Write compound tags ordered by name rather than as stored.
+    pub sort_keys: bool,
+}
+
+impl Format {
+    pub const fn compact() -> Self {
+        Self { indent: None, sort_keys: false }
+    }
+
+    /// One tag or compound list element per line, indented by four spaces.
+    pub const fn pretty() -> Self {
+        Self { indent: Some(4), sort_keys: false }
+    }
+}
+
+pub fn to_string(p: &Payload) -> String {
+    to_string_with(p, Format::default())
+}
+
+pub fn to_string_with(p: &Payload, format: Format) -> String {
+    let mut out = String::new();
+    write(&mut out, p, format).expect("writing to a String cannot fail");
+    out
+}
+
+pub fn write<W: Write>(w: &mut W, p: &Payload, format: Format) -> fmt::Result {
+    Printer { w, format, level: 0 }.payload(p)
+}
+
+/// Compact SNBT, or pretty with `{:#}`.
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = match f.alternate() {
+            true => Format::pretty(),
+            false => Format::compact(),
+        };
+        write(f, self, format)
+    }
+}
+
+struct Printer<'w, W> {
+    w: &'w mut W,
+    format: Format,
+    level: usize,
+}
+
+impl<W: Write> Printer<'_, W> {
+    fn payload(&mut self, p: &Payload) -> fmt::Result {
+        match p {
+            // Only ever the root of a nameless tag, which SNBT cannot express
+            Payload::End => Ok(()),
+            Payload::Byte(x) => write!(self.w, "{}b", x),
+            Payload::Short(x) => write!(self.w, "{}s", x),
+            Payload::Int(x) => write!(self.w, "{}", x),
+            Payload::Long(x) => write!(self.w, "{}L", x),
+            Payload::Float(x) => float(self.w, *x as f64, format_args!("{:?}", x), 'f'),
+            Payload::Double(x) => float(self.w, *x, format_args!("{:?}", x), 'd'),
+            Payload::ByteArray(x) => self.array('B', x.iter().map(|&b| format!("{}B", b as i8))),
+            Payload::String(x) => quoted(self.w, x),
+            Payload::List(_, x) => self.list(x),
+            Payload::Compound(x) => self.compound(x),
+            Payload::IntArray(x) => self.array('I', x.iter().map(|i| i.to_string())),
+            Payload::LongArray(x) => self.array('L', x.iter().map(|l| format!("{}L", l))),
+        }
+    }
+
+    /// Starts a new line at the current level, if pretty printing.
+    fn newline(&mut self) -> fmt::Result {
+        match self.format.indent {
+            Some(n) => write!(self.w, "\n{:1$}", "", n * self.level),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the separator in front of an inline value other than the first.
+    fn space(&mut self, first: bool) -> fmt::Result {
+        if !first {
+            self.w.write_char(',')?;
+        }
+        match self.format.indent {
+            Some(_) if !first => self.w.write_char(' '),
+            _ => Ok(()),
+        }
+    }
+
+    fn array(&mut self, kind: char, items: impl Iterator<Item = String>) -> fmt::Result {
+        write!(self.w, "[{};", kind)?;
+        for (i, item) in items.enumerate() {
+            if i == 0 && self.format.indent.is_some() {
+                self.w.write_char(' ')?;
+            }
+            self.space(i == 0)?;
+            self.w.write_str(&item)?;
+        }
+        self.w.write_char(']')
+    }
+
+    fn list(&mut self, items: &[Payload]) -> fmt::Result {
+        // Numbers and strings stay on one line even when pretty printing
+        let nested = items.iter().any(|p| matches!(p, Payload::List(..) | Payload::Compound(_)));
+        self.w.write_char('[')?;
+        self.level += 1;
+        for (i, item) in items.iter().enumerate() {
+            match nested {
+                true if i > 0 => self.w.write_char(',')?,
+                true => {}
+                false => self.space(i == 0)?,
+            }
+            if nested {
+                self.newline()?;
+            }
+            self.payload(item)?;
+        }
+        self.level -= 1;
+        if nested {
+            self.newline()?;
+        }
+        self.w.write_char(']')
+    }
+
+    fn compound(&mut self, tags: &[Tag]) -> fmt::Result {
+        let mut tags: Vec<_> = tags.iter().collect();
+        if self.format.sort_keys {
+            tags.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        self.w.write_char('{')?;
+        self.level += 1;
+        for (i, tag) in tags.iter().enumerate() {
+            if i > 0 {
+                self.w.write_char(',')?;
+            }
+            self.newline()?;
+            key(self.w, &tag.name)?;
+            self.w.write_str(if self.format.indent.is_some() { ": " } else { ":" })?;
+            self.payload(&tag.payload)?;
+        }
+        self.level -= 1;
+        if !tags.is_empty() {
+            self.newline()?;
+        }
+        self.w.write_char('}')
+    }
+}
+
+/// Writes `debug`, Rust's shortest representation of `x` that parses back
+/// to the same value, with `suffix`. Infinities and NaN are written as Java
+/// does, though they read back as strings.
+fn float<W: Write>(w: &mut W, x: f64, debug: fmt::Arguments<'_>, suffix: char) -> fmt::Result {
+    match x {
+        x if x.is_nan() => write!(w, "NaN{}", suffix),
+        f64::INFINITY => write!(w, "Infinity{}", suffix),
+        f64::NEG_INFINITY => write!(w, "-Infinity{}", suffix),
+        _ => write!(w, "{}{}", debug, suffix),
+    }
+}
+
+fn key<W: Write>(w: &mut W, name: &str) -> fmt::Result {
+    let bare = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'));
+    match bare {
+        true => w.write_str(name),
+        false => quoted(w, name),
+    }
+}
+
+/// Quotes with `"` unless only `'` avoids escaping, as vanilla does.
+fn quoted<W: Write>(w: &mut W, s: &str) -> fmt::Result {
+    let quote = if s.contains('"') && !s.contains('\'') { '\'' } else { '"' };
+    w.write_char(quote)?;
+    for c in s.chars() {
+        match c {
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if c == quote => write!(w, "\\{}", c)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char(quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snbt::parse;
+
+    fn item() -> Payload {
+        Payload::Compound(vec![
+            Tag::new("id", Payload::String("minecraft:stone".into())),
+            Tag::new("Count", Payload::Byte(1)),
+            Tag::new("tag", Payload::Compound(vec![
+                Tag::new("display name", Payload::String(r#"say "hi""#.into())),
+                Tag::new("Pos", Payload::list(vec![Payload::Double(0.5), Payload::Double(-64.0)])),
+                Tag::new("Items", Payload::list(vec![Payload::Compound(vec![]), Payload::Compound(vec![])])),
+                Tag::new("B", Payload::ByteArray(vec![1, 255])),
+                Tag::new("L", Payload::LongArray(vec![])),
+            ])),
+        ])
+    }
+
+    #[test]
+    fn compact() {
+        assert_eq!(
+            to_string(&item()),
+            r#"{id:"minecraft:stone",Count:1b,tag:{"display name":'say "hi"',Pos:[0.5d,-64.0d],Items:[{},{}],B:[B;1B,-1B],L:[L;]}}"#
+        );
+        assert_eq!(Payload::IntArray(vec![1, -2]).to_string(), "[I;1,-2]");
+        let sorted = Format { sort_keys: true, ..Format::compact() };
+        assert_eq!(to_string_with(&item(), sorted).split(',').next(), Some("{Count:1b"));
+    }
+
+    #[test]
+    fn pretty() {
+        assert_eq!(format!("{:#}", item()), r#"{
+    id: "minecraft:stone",
+    Count: 1b,
+    tag: {
+        "display name": 'say "hi"',
+        Pos: [0.5d, -64.0d],
+        Items: [
+            {},
+            {}
+        ],
+        B: [B; 1B, -1B],
+        L: [L;]
+    }
+}"#);
+        assert_eq!(parse(&format!("{:#}", item())).unwrap(), item());
+    }
+
+    #[test]
+    fn round_trip() {
+        let values = [
+            item(),
+            Payload::Float(0.1),
+            Payload::Float(1e-45),
+            Payload::Float(f32::MAX),
+            Payload::Float(-0.0),
+            Payload::Double(0.1 + 0.2),
+            Payload::Double(1e300),
+            Payload::Double(f64::MIN_POSITIVE),
+            Payload::Long(i64::MIN),
+            Payload::String("it's a \"quote\" \\ \n".into()),
+            Payload::String("true".into()),
+            Payload::List(0, vec![]),
+            Payload::Compound(vec![Tag::new("", Payload::Int(1)), Tag::new("1b", Payload::Int(2))]),
+        ];
+        for p in values {
+            let text = to_string(&p);
+            assert_eq!(parse(&text).unwrap(), p, "{}", text);
+        }
+        assert_eq!(Payload::Float(f32::INFINITY).to_string(), "Infinityf");
+    }
+}
@@ -0,0 +1,8 @@
+//! Stringified NBT, the text form used by commands, datapacks and
+//! `/data get`, such as `{Count:1b,id:"minecraft:stone",tag:{Damage:0}}`.
+
+mod format;
+mod parse;
+
+pub use format::{to_string, to_string_with, write, Format};
+pub use parse::{parse, parse_with, tag};
@@ -0,0 +1,406 @@
+//! Parsing SNBT the way vanilla does.
+
+use std::str::FromStr;
+
+use crate::error::{Error, Path, Reason, Segment};
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+use crate::parser::Limits;
+
+/// Parses one SNBT value spanning all of `text`, apart from whitespace.
+pub fn parse(text: &str) -> Result<Payload, Error> {
+    parse_with(text, Limits::default())
+}
+
+/// Parses with `limits.depth` and `limits.length` enforced.
+pub fn parse_with(text: &str, limits: Limits) -> Result<Payload, Error> {
+    let mut p = Parser { text, pos: 0, limits, depth: 0, path: Path::default() };
+    let value = p.value()?;
+    p.whitespace();
+    match p.pos < text.len() {
+        true => Err(p.fail("expected end of input", p.pos)),
+        false => Ok(value),
+    }
+}
+
+/// Parses a compound as an unnamed root tag, as `/data merge` expects.
+pub fn tag(text: &str) -> Result<Tag, Error> {
+    match parse(text)? {
+        payload @ Payload::Compound(_) => Ok(Tag::new("", payload)),
+        other => Err(Error::new(Reason::RootNotCompound(other.id()), 0, Path::default())),
+    }
+}
+
+impl FromStr for Payload {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        parse(s)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of the next character.
+    pos: usize,
+    limits: Limits,
+    depth: usize,
+    path: Path,
+}
+
+impl Parser<'_> {
+    fn fail(&self, msg: &str, at: usize) -> Error {
+        Error::new(Reason::Syntax(msg.into()), at, self.path.clone())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, c: char, msg: &str) -> Result<(), Error> {
+        self.whitespace();
+        match self.peek() == Some(c) {
+            true => {
+                self.bump();
+                Ok(())
+            }
+            false => Err(self.fail(msg, self.pos)),
+        }
+    }
+
+    /// Consumes a separating comma, or returns false before `close`.
+    fn separator(&mut self, close: char, msg: &str) -> Result<bool, Error> {
+        self.whitespace();
+        let more = match self.peek() {
+            Some(',') => true,
+            Some(c) if c == close => false,
+            _ => return Err(self.fail(msg, self.pos)),
+        };
+        self.bump();
+        Ok(more)
+    }
+
+    fn value(&mut self) -> Result<Payload, Error> {
+        self.whitespace();
+        let at = self.pos;
+        match self.peek() {
+            Some('{') => self.compound(),
+            Some('[') => self.list(),
+            Some('"' | '\'') => self.quoted().map(Payload::String),
+            _ => match self.unquoted() {
+                "" => Err(self.fail("expected a value", at)),
+                s => Ok(typed(s)),
+            },
+        }
+    }
+
+    fn unquoted(&mut self) -> &str {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')) {
+            self.bump();
+        }
+        &self.text[start..self.pos]
+    }
+
+    fn quoted(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        let quote = self.bump();
+        let mut out = String::new();
+        loop {
+            let at = self.pos;
+            match self.bump() {
+                None => return Err(self.fail("unterminated string", start)),
+                Some('\\') => match self.bump() {
+                    Some(c @ ('\\' | '"' | '\'')) => out.push(c),
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    _ => return Err(self.fail("invalid escape", at)),
+                },
+                c if c == quote => return Ok(out),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, Error> {
+        self.whitespace();
+        let at = self.pos;
+        match self.peek() {
+            Some('"' | '\'') => self.quoted(),
+            _ => match self.unquoted() {
+                "" => Err(self.fail("expected a key", at)),
+                s => Ok(s.into()),
+            },
+        }
+    }
+
+    /// Enters a compound or list starting at `at`.
+    fn enter(&mut self, at: usize) -> Result<(), Error> {
+        if self.depth >= self.limits.depth {
+            return Err(Error::new(Reason::DepthLimit, at, self.path.clone()));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn length(&self, len: usize, at: usize) -> Result<(), Error> {
+        match len > self.limits.length {
+            true => Err(Error::new(Reason::LengthLimit(len), at, self.path.clone())),
+            false => Ok(()),
+        }
+    }
+
+    fn compound(&mut self) -> Result<Payload, Error> {
+        let at = self.pos;
+        self.enter(at)?;
+        self.bump();
+        let mut tags: Vec<Tag> = Vec::new();
+        self.whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+        } else {
+            loop {
+                let name = self.key()?;
+                self.expect(':', "expected ':'")?;
+                self.path.push(Segment::Name(name.clone()));
+                let payload = self.value()?;
+                self.path.pop();
+                // Later duplicates replace earlier ones, as in vanilla
+                match tags.iter_mut().find(|t| t.name == name) {
+                    Some(t) => t.payload = payload,
+                    None => tags.push(Tag::new(name, payload)),
+                }
+                self.length(tags.len(), at)?;
+                if !self.separator('}', "expected ',' or '}'")? {
+                    break;
+                }
+                self.whitespace();
+                if self.peek() == Some('}') {
+                    self.bump();
+                    break;
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(Payload::Compound(tags))
+    }
+
+    fn list(&mut self) -> Result<Payload, Error> {
+        let at = self.pos;
+        self.enter(at)?;
+        self.bump();
+        let rest = &self.text[self.pos..];
+        let array = match (rest.as_bytes().first(), rest.as_bytes().get(1)) {
+            (Some(&c), Some(b';')) if !matches!(c, b'"' | b'\'') => Some(c),
+            _ => None,
+        };
+        let payload = match array {
+            Some(c) => {
+                self.pos += 2;
+                self.array(c as char)?
+            }
+            None => self.items(at)?,
+        };
+        self.depth -= 1;
+        Ok(payload)
+    }
+
+    /// Reads the elements of a list up to and including the closing bracket.
+    fn elements(&mut self, at: usize, mut f: impl FnMut(&mut Self) -> Result<(), Error>) -> Result<(), Error> {
+        self.whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(());
+        }
+        for i in 0.. {
+            self.length(i + 1, at)?;
+            self.path.push(Segment::Index(i));
+            f(self)?;
+            self.path.pop();
+            if !self.separator(']', "expected ',' or ']'")? {
+                break;
+            }
+            self.whitespace();
+            if self.peek() == Some(']') {
+                self.bump();
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn items(&mut self, at: usize) -> Result<Payload, Error> {
+        let mut items: Vec<Payload> = Vec::new();
+        self.elements(at, |p| {
+            p.whitespace();
+            let start = p.pos;
+            let item = p.value()?;
+            match items.first() {
+                Some(first) if first.id() != item.id() => Err(p.fail("list elements must have the same type", start)),
+                _ => {
+                    items.push(item);
+                    Ok(())
+                }
+            }
+        })?;
+        Ok(items.into())
+    }
+
+    fn array(&mut self, kind: char) -> Result<Payload, Error> {
+        let at = self.pos - 3;
+        let (mut out, msg) = match kind {
+            'B' => (Payload::ByteArray(Vec::new()), "expected a byte"),
+            'I' => (Payload::IntArray(Vec::new()), "expected an int"),
+            'L' => (Payload::LongArray(Vec::new()), "expected a long"),
+            _ => return Err(self.fail("invalid array type", at + 1)),
+        };
+        self.elements(at, |p| {
+            p.whitespace();
+            let start = p.pos;
+            match (&mut out, p.value()?) {
+                (Payload::ByteArray(v), Payload::Byte(x)) => v.push(x as u8),
+                (Payload::IntArray(v), Payload::Int(x)) => v.push(x),
+                (Payload::LongArray(v), Payload::Long(x)) => v.push(x),
+                _ => return Err(p.fail(msg, start)),
+            }
+            Ok(())
+        })?;
+        Ok(out)
+    }
+}
+
+/// The type of an unquoted value. Anything that is not a well formed
+/// number in range, nor `true` or `false`, is a string.
+fn typed(s: &str) -> Payload {
+    let number = match s.char_indices().last() {
+        Some((i, suffix)) => match (suffix.to_ascii_lowercase(), &s[..i]) {
+            ('b', body) if integer(body) => body.parse().ok().map(Payload::Byte),
+            ('s', body) if integer(body) => body.parse().ok().map(Payload::Short),
+            ('l', body) if integer(body) => body.parse().ok().map(Payload::Long),
+            ('f', body) if decimal(body, false) => body.parse().ok().map(Payload::Float),
+            ('d', body) if decimal(body, false) => body.parse().ok().map(Payload::Double),
+            _ if integer(s) => s.parse().ok().map(Payload::Int),
+            _ if decimal(s, true) => s.parse().ok().map(Payload::Double),
+            _ => None,
+        },
+        None => None,
+    };
+    match number {
+        Some(p) => p,
+        None if s.eq_ignore_ascii_case("true") => Payload::Byte(1),
+        None if s.eq_ignore_ascii_case("false") => Payload::Byte(0),
+        None => Payload::String(s.into()),
+    }
+}
+
+fn unsigned(s: &str) -> &str {
+    s.strip_prefix(['+', '-']).unwrap_or(s)
+}
+
+/// `[-+]?(0|[1-9][0-9]*)`
+fn integer(s: &str) -> bool {
+    let s = unsigned(s);
+    s == "0" || (s.starts_with(|c: char| matches!(c, '1'..='9')) && s.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// `[-+]?([0-9]+[.]?|[0-9]*[.][0-9]+)(e[-+]?[0-9]+)?`, needing the point
+/// when `point` is set.
+fn decimal(s: &str, point: bool) -> bool {
+    let s = unsigned(s);
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(unsigned(&s[i + 1..]))),
+        None => (s, None),
+    };
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let mantissa = match mantissa.split_once('.') {
+        Some((a, b)) => digits(a) && digits(b) && !(a.is_empty() && b.is_empty()),
+        None => !point && !mantissa.is_empty() && digits(mantissa),
+    };
+    mantissa && exponent.is_none_or(|e| !e.is_empty() && digits(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let item = parse(r#"{Count:1b,id:"minecraft:stone",tag:{Damage:0}}"#).unwrap();
+        assert_eq!(item, Payload::Compound(vec![
+            Tag::new("Count", Payload::Byte(1)),
+            Tag::new("id", Payload::String("minecraft:stone".into())),
+            Tag::new("tag", Payload::Compound(vec![Tag::new("Damage", Payload::Int(0))])),
+        ]));
+
+        let cases = [
+            ("12b", Payload::Byte(12)),
+            ("-3S", Payload::Short(-3)),
+            ("+7", Payload::Int(7)),
+            ("9000000000L", Payload::Long(9_000_000_000)),
+            ("1.5f", Payload::Float(1.5)),
+            ("2d", Payload::Double(2.0)),
+            (".5", Payload::Double(0.5)),
+            ("1.e3", Payload::Double(1000.0)),
+            ("true", Payload::Byte(1)),
+            ("FALSE", Payload::Byte(0)),
+            ("300b", Payload::String("300b".into())),
+            ("007", Payload::String("007".into())),
+            ("1e3", Payload::String("1e3".into())),
+            ("minecraft.stone", Payload::String("minecraft.stone".into())),
+            (r#""a \"b\" \\ c""#, Payload::String(r#"a "b" \ c"#.into())),
+            (r#"'it\'s "x"'"#, Payload::String(r#"it's "x""#.into())),
+            ("[B; 1b, -1b]", Payload::ByteArray(vec![1, 255])),
+            ("[I;1,2,]", Payload::IntArray(vec![1, 2])),
+            ("[L;]", Payload::LongArray(vec![])),
+            ("[]", Payload::List(0, vec![])),
+            (" [ 1s , 2s ] ", Payload::List(2, vec![Payload::Short(1), Payload::Short(2)])),
+            ("[[1], [a]]", Payload::list(vec![Payload::list(vec![Payload::Int(1)]), Payload::list(vec![Payload::String("a".into())])])),
+            ("{ 'my key' : {}, a:1, a:2 }", Payload::Compound(vec![
+                Tag::new("my key", Payload::Compound(vec![])),
+                Tag::new("a", Payload::Int(2)),
+            ])),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse(text).unwrap(), expected, "{}", text);
+        }
+        assert_eq!("{}".parse::<Payload>().unwrap(), Payload::Compound(vec![]));
+        assert_eq!(tag("{a:1}").unwrap().payload, Payload::Compound(vec![Tag::new("a", Payload::Int(1))]));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| {
+            let e = parse(text).unwrap_err();
+            (e.to_string(), e.offset())
+        };
+        assert_eq!(error("{a 1}"), ("expected ':' at byte 3".into(), 3));
+        assert_eq!(error("{a:1 b:2}"), ("expected ',' or '}' at byte 5".into(), 5));
+        assert_eq!(error("{a:[1,2b]}"), ("list elements must have the same type at byte 6 in a[1]".into(), 6));
+        assert_eq!(error("[I;1,2L]"), ("expected an int at byte 5 in [1]".into(), 5));
+        assert_eq!(error("[X;1]"), ("invalid array type at byte 1".into(), 1));
+        assert_eq!(error("{a:\"x}"), ("unterminated string at byte 3 in a".into(), 3));
+        assert_eq!(error("'\\q'"), ("invalid escape at byte 1".into(), 1));
+        assert_eq!(error("{:1}"), ("expected a key at byte 1".into(), 1));
+        assert_eq!(error("{a:}"), ("expected a value at byte 3 in a".into(), 3));
+        assert_eq!(error("1 2"), ("expected end of input at byte 2".into(), 2));
+        assert_eq!(error(""), ("expected a value at byte 0".into(), 0));
+
+        let deep = Limits { depth: 2, ..Limits::default() };
+        let e = parse_with("{a:[{}]}", deep).unwrap_err();
+        assert_eq!((e.reason(), e.path().to_string()), (&Reason::DepthLimit, "a[0]".into()));
+        assert!(matches!(tag("[]").unwrap_err().reason(), Reason::RootNotCompound(9)));
+    }
+}
@@ -0,0 +1,523 @@
+//! Parsing from an `io::Read`, such as a decompressing stream, without
+//! holding the whole input in memory.
+//!
+//! Produces the same trees as `parser`, and errors with the same reasons
+//! and paths.
+
+use std::io::{self, Read};
+
+use crate::error::{Error, Path, Reason, Segment};
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+use crate::parser::{raw, Encoding, Options};
+
+/// Reads NBT from `R`, tracking the offset and tag path for errors.
+pub struct Reader<R> {
+    inner: R,
+    opts: Options,
+    offset: usize,
+    /// Offset the current root tag started at, for `Limits::bytes`.
+    start: usize,
+    path: Path,
+    depth: usize,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R, opts: Options) -> Self {
+        Self { inner, opts, offset: 0, start: 0, path: Path::default(), depth: 0 }
+    }
+
+    /// Bytes read so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads a root tag, named or not as `Options::nameless_root` says; see
+    /// `parser::parse`.
+    pub fn root(&mut self) -> Result<Tag, Error> {
+        match self.opts.nameless_root {
+            true => self.nameless().map(|payload| Tag::new("", payload)),
+            false => self.tag(),
+        }
+    }
+
+    pub fn tag(&mut self) -> Result<Tag, Error> {
+        self.begin();
+        let (id, name) = self.header()?;
+        let payload = self.payload(id)?;
+        Ok(Tag::new(name, payload))
+    }
+
+    /// A root tag without a name; see `tag::nameless`.
+    pub fn nameless(&mut self) -> Result<Payload, Error> {
+        self.begin();
+        let at = self.offset;
+        match self.byte()? {
+            0 => Ok(Payload::End),
+            id @ 1..=12 => self.payload(id),
+            id => Err(self.fail(Reason::UnknownTag(id), at)),
+        }
+    }
+
+    /// Starts a root tag as `root` does, reading up to its payload and
+    /// returning the payload's id.
+    #[cfg(feature = "serde")]
+    pub(crate) fn root_id(&mut self) -> Result<i8, Error> {
+        self.begin();
+        let at = self.offset;
+        let id = self.byte()?;
+        match (self.opts.nameless_root, id) {
+            (true, 0..=12) => Ok(id),
+            (true, _) => Err(self.fail(Reason::UnknownTag(id), at)),
+            (false, _) => Ok(self.named(id, at)?.0),
+        }
+    }
+
+    /// The id and name in front of a payload.
+    pub fn header(&mut self) -> Result<(i8, String), Error> {
+        let at = self.offset;
+        let id = self.byte()?;
+        self.named(id, at)
+    }
+
+    /// Reads the name after the id `id` read at `at`.
+    pub(crate) fn named(&mut self, id: i8, at: usize) -> Result<(i8, String), Error> {
+        if !(1..=12).contains(&id) {
+            return Err(self.fail(Reason::UnknownTag(id), at));
+        }
+        let len = self.string_length()?;
+        let name = self.string(len)?;
+        Ok((id, name))
+    }
+
+    pub fn payload(&mut self, id: i8) -> Result<Payload, Error> {
+        Ok(match id {
+            1 => Payload::Byte(self.byte()?),
+            2 => Payload::Short(self.short()?),
+            3 => Payload::Int(self.int()?),
+            4 => Payload::Long(self.long()?),
+            5 => Payload::Float(self.float()?),
+            6 => Payload::Double(self.double()?),
+            7 => {
+                let len = self.length(1)?;
+                Payload::ByteArray(self.bytes(len)?)
+            }
+            8 => {
+                let len = self.string_length()?;
+                Payload::String(self.string(len)?)
+            }
+            9 => self.list()?,
+            10 => self.compound()?,
+            11 => {
+                let len = self.length(self.opts.encoding.width(4))?;
+                Payload::IntArray((0..len).map(|_| self.int()).collect::<Result<_, _>>()?)
+            }
+            12 => {
+                let len = self.length(self.opts.encoding.width(8))?;
+                Payload::LongArray((0..len).map(|_| self.long()).collect::<Result<_, _>>()?)
+            }
+            _ => return Err(self.fail(Reason::UnknownTag(id), self.offset)),
+        })
+    }
+
+    /// Reads past a payload without decoding it; see `parser::skip`.
+    pub fn skip(&mut self, id: i8) -> Result<(), Error> {
+        let enc = self.opts.encoding;
+        match id {
+            1 => self.discard(1),
+            2 => self.discard(2),
+            3 if enc == Encoding::Network => self.int().map(drop),
+            4 if enc == Encoding::Network => self.long().map(drop),
+            3 | 5 => self.discard(4),
+            4 | 6 => self.discard(8),
+            7 => {
+                let len = self.length(1)?;
+                self.discard(len)
+            }
+            8 => {
+                let len = self.string_length()?;
+                self.discard(len)
+            }
+            9 => {
+                let (id, len) = self.list_header()?;
+                self.skip_elements(id, len)?;
+                self.leave();
+                Ok(())
+            }
+            10 => {
+                self.enter(self.offset)?;
+                self.skip_compound()
+            }
+            11 => {
+                let len = self.length(enc.width(4))?;
+                self.skip_elements(3, len)
+            }
+            12 => {
+                let len = self.length(enc.width(8))?;
+                self.skip_elements(4, len)
+            }
+            _ => Err(self.fail(Reason::UnknownTag(id), self.offset)),
+        }
+    }
+
+    /// Skips the rest of a compound that has been entered, up to and
+    /// including its end tag.
+    pub(crate) fn skip_compound(&mut self) -> Result<(), Error> {
+        loop {
+            let at = self.offset;
+            match self.byte()? {
+                0 => break,
+                id @ 1..=12 => {
+                    let len = self.string_length()?;
+                    self.discard(len)?;
+                    self.skip(id)?;
+                }
+                id => return Err(self.fail(Reason::UnknownTag(id), at)),
+            }
+        }
+        self.leave();
+        Ok(())
+    }
+
+    /// Skips `len` payloads with id `id`.
+    pub(crate) fn skip_elements(&mut self, id: i8, len: usize) -> Result<(), Error> {
+        let width = match (id, self.opts.encoding) {
+            (3 | 4, Encoding::Network) | (7..=12, _) => None,
+            (1, _) => Some(1),
+            (2, _) => Some(2),
+            (3 | 5, _) => Some(4),
+            _ => Some(8),
+        };
+        match width {
+            Some(w) => self.discard(len.saturating_mul(w)),
+            None => (0..len).try_for_each(|_| self.skip(id)),
+        }
+    }
+
+    fn list(&mut self) -> Result<Payload, Error> {
+        let (id, len) = self.list_header()?;
+        // The count is untrusted and the input length unknown, so grow as
+        // elements actually arrive
+        let mut items = Vec::with_capacity(len.min(1024));
+        for i in 0..len {
+            self.path.push(Segment::Index(i));
+            items.push(self.payload(id)?);
+            self.path.pop();
+        }
+        self.leave();
+        Ok(Payload::List(id, items))
+    }
+
+    fn compound(&mut self) -> Result<Payload, Error> {
+        self.enter(self.offset)?;
+        let mut tags = Vec::new();
+        loop {
+            let at = self.offset;
+            let (id, name) = match self.byte()? {
+                0 => break,
+                id => self.named(id, at)?,
+            };
+            self.path.push(Segment::Name(name));
+            let payload = self.payload(id)?;
+            let Some(Segment::Name(name)) = self.path.pop() else { unreachable!() };
+            tags.push(Tag::new(name, payload));
+        }
+        self.leave();
+        Ok(Payload::Compound(tags))
+    }
+
+    /// Enters a list, returning its element id and length.
+    pub(crate) fn list_header(&mut self) -> Result<(i8, usize), Error> {
+        let at = self.offset;
+        self.enter(at)?;
+        let id = self.byte()?;
+        let len = self.length(1)?;
+        if !(0..=12).contains(&id) || (id == 0 && len > 0) {
+            return Err(self.fail(Reason::UnknownTag(id), at));
+        }
+        Ok((id, len))
+    }
+
+    /// Starts a root tag, which has its own byte budget.
+    pub(crate) fn begin(&mut self) {
+        self.start = self.offset;
+    }
+
+    /// Enters a compound or list starting at `at`.
+    pub(crate) fn enter(&mut self, at: usize) -> Result<(), Error> {
+        if self.depth >= self.opts.limits.depth {
+            return Err(self.fail(Reason::DepthLimit, at));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Path of the tag being read, reported with errors.
+    pub(crate) fn path_mut(&mut self) -> &mut Path {
+        &mut self.path
+    }
+
+    pub(crate) fn options(&self) -> Options {
+        self.opts
+    }
+
+    pub fn byte(&mut self) -> Result<i8, Error> {
+        Ok(self.array::<1>()?[0] as i8)
+    }
+
+    pub fn short(&mut self) -> Result<i16, Error> {
+        let b = self.array()?;
+        Ok(match self.opts.encoding {
+            Encoding::BigEndian => i16::from_be_bytes(b),
+            Encoding::LittleEndian | Encoding::Network => i16::from_le_bytes(b),
+        })
+    }
+
+    pub fn int(&mut self) -> Result<i32, Error> {
+        Ok(match self.opts.encoding {
+            Encoding::BigEndian => i32::from_be_bytes(self.array()?),
+            Encoding::LittleEndian => i32::from_le_bytes(self.array()?),
+            Encoding::Network => {
+                let at = self.offset;
+                let x = raw::zigzag(self.varint(5)?);
+                i32::try_from(x).map_err(|_| self.fail(Reason::Malformed, at))?
+            }
+        })
+    }
+
+    pub fn long(&mut self) -> Result<i64, Error> {
+        Ok(match self.opts.encoding {
+            Encoding::BigEndian => i64::from_be_bytes(self.array()?),
+            Encoding::LittleEndian => i64::from_le_bytes(self.array()?),
+            Encoding::Network => raw::zigzag(self.varint(10)?),
+        })
+    }
+
+    pub fn float(&mut self) -> Result<f32, Error> {
+        let b = self.array()?;
+        Ok(match self.opts.encoding {
+            Encoding::BigEndian => f32::from_be_bytes(b),
+            Encoding::LittleEndian | Encoding::Network => f32::from_le_bytes(b),
+        })
+    }
+
+    pub fn double(&mut self) -> Result<f64, Error> {
+        let b = self.array()?;
+        Ok(match self.opts.encoding {
+            Encoding::BigEndian => f64::from_be_bytes(b),
+            Encoding::LittleEndian | Encoding::Network => f64::from_le_bytes(b),
+        })
+    }
+
+    /// Number of elements in an array or list whose elements take at least
+    /// `width` bytes each.
+    pub fn length(&mut self, width: usize) -> Result<usize, Error> {
+        let at = self.offset;
+        let len = self.int()?;
+        let len = usize::try_from(len).map_err(|_| self.fail(Reason::NegativeLength(len), at))?;
+        if len > self.opts.limits.length {
+            return Err(self.fail(Reason::LengthLimit(len), at));
+        }
+        if len.saturating_mul(width) > self.budget() {
+            return Err(self.fail(Reason::SizeLimit, at));
+        }
+        Ok(len)
+    }
+
+    /// Number of bytes in a string.
+    pub fn string_length(&mut self) -> Result<usize, Error> {
+        Ok(match self.opts.encoding {
+            Encoding::BigEndian => u16::from_be_bytes(self.array()?) as usize,
+            Encoding::LittleEndian => u16::from_le_bytes(self.array()?) as usize,
+            Encoding::Network => {
+                let at = self.offset;
+                let x = self.varint(5)?;
+                u32::try_from(x).map_err(|_| self.fail(Reason::Malformed, at))? as usize
+            }
+        })
+    }
+
+    /// A string of `len` bytes, decoded as `parser` would.
+    pub fn string(&mut self, len: usize) -> Result<String, Error> {
+        let at = self.offset;
+        let bytes = self.bytes(len)?;
+        let decoded = match self.opts.encoding.string(self.opts.strings, len)(&bytes) {
+            Ok((_, s)) => Ok(s.into_owned()),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err((e.reason, at + len - e.input.len())),
+            Err(nom::Err::Incomplete(_)) => Err((Reason::Truncated, self.offset)),
+        };
+        decoded.map_err(|(reason, offset)| self.fail(reason, offset))
+    }
+
+    /// Reads `len` bytes, allocating only as they arrive so a bogus length
+    /// fails with `Truncated` rather than exhausting memory.
+    pub fn bytes(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        if len > self.budget() {
+            return Err(self.fail(Reason::SizeLimit, self.offset));
+        }
+        let mut out = Vec::new();
+        let read = (&mut self.inner).take(len as u64).read_to_end(&mut out);
+        self.offset += out.len();
+        match read {
+            Err(e) => Err(Error::io(e, self.offset, self.path.clone())),
+            Ok(n) if n < len => Err(self.fail(Reason::Truncated, self.offset)),
+            Ok(_) => Ok(out),
+        }
+    }
+
+    /// Reads past `len` bytes.
+    fn discard(&mut self, len: usize) -> Result<(), Error> {
+        if len > self.budget() {
+            return Err(self.fail(Reason::SizeLimit, self.offset));
+        }
+        let read = io::copy(&mut (&mut self.inner).take(len as u64), &mut io::sink());
+        match read {
+            Err(e) => Err(Error::io(e, self.offset, self.path.clone())),
+            Ok(n) => {
+                self.offset += n as usize;
+                match n as usize == len {
+                    true => Ok(()),
+                    false => Err(self.fail(Reason::Truncated, self.offset)),
+                }
+            }
+        }
+    }
+
+    /// Unsigned LEB128 VarInt of at most `max` bytes.
+    fn varint(&mut self, max: usize) -> Result<u64, Error> {
+        let at = self.offset;
+        let mut x = 0;
+        for i in 0..max {
+            let b = self.array::<1>()?[0];
+            x |= ((b & 0x7F) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok(x);
+            }
+        }
+        Err(self.fail(Reason::VarIntTooLong, at))
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        if N > self.budget() {
+            return Err(self.fail(Reason::SizeLimit, self.offset));
+        }
+        let mut b = [0; N];
+        let mut filled = 0;
+        while filled < N {
+            match self.inner.read(&mut b[filled..]) {
+                Ok(0) => return Err(self.fail(Reason::Truncated, self.offset + filled)),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::io(e, self.offset + filled, self.path.clone())),
+            }
+        }
+        self.offset += N;
+        Ok(b)
+    }
+
+    /// Bytes that may still be read for the current root tag.
+    fn budget(&self) -> usize {
+        self.opts.limits.bytes.saturating_sub(self.offset - self.start)
+    }
+
+    pub(crate) fn fail(&self, reason: Reason, offset: usize) -> Error {
+        Error::new(reason, offset, self.path.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use crate::parser::{self, Limits};
+    use crate::writer;
+
+    fn root() -> Tag {
+        Tag::new("root", Payload::Compound(vec![
+            Tag::new("b", Payload::Byte(-1)),
+            Tag::new("f", Payload::Float(1.5)),
+            Tag::new("d", Payload::Double(-2.25)),
+            Tag::new("s", Payload::String("caf\u{e9}\0".into())),
+            Tag::new("bytes", Payload::ByteArray(vec![1, 2, 3])),
+            Tag::new("ints", Payload::IntArray(vec![1, -2, i32::MAX])),
+            Tag::new("longs", Payload::LongArray(vec![i64::MIN, 5])),
+            Tag::new("list", Payload::list(vec![Payload::Compound(vec![Tag::new("x", Payload::Short(3))])])),
+            Tag::new("empty", Payload::List(0, vec![])),
+        ]))
+    }
+
+    #[test]
+    fn encodings() {
+        for encoding in [Encoding::BigEndian, Encoding::LittleEndian, Encoding::Network] {
+            let opts = Options { encoding, ..Options::default() };
+            let mut data = Vec::new();
+            writer::tag_with(&mut data, &root(), encoding).unwrap();
+            data.push(0xFF);
+            let mut reader = Reader::new(&data[..], opts);
+            assert_eq!(reader.tag().unwrap(), root());
+            assert_eq!(reader.offset(), data.len() - 1);
+        }
+    }
+
+    #[test]
+    fn compressed() {
+        let mut data = Vec::new();
+        writer::tag(&mut data, &root()).unwrap();
+        let gz = Compression::Gzip.compress(&data).unwrap();
+        let mut reader = Reader::new(Compression::Gzip.decoder(&gz[..]).unwrap(), Options::default());
+        assert_eq!(reader.root().unwrap(), root());
+    }
+
+    #[test]
+    fn errors() {
+        let mut data = Vec::new();
+        writer::tag(&mut data, &root()).unwrap();
+        for end in [0, 5, 12, 40, 60, data.len() - 1] {
+            let e = Reader::new(&data[..end], Options::default()).tag().unwrap_err();
+            let expected = parser::parse(&data[..end], Options::default()).unwrap_err();
+            assert_eq!((e.reason(), e.path()), (expected.reason(), expected.path()));
+        }
+
+        let bad = [0x0A, 0, 0, 0x08, 0, 1, b's', 0, 1, 0xFF, 0];
+        let e = Reader::new(&bad[..], Options::default()).tag().unwrap_err();
+        assert_eq!((e.reason(), e.offset(), e.path().to_string()), (&Reason::InvalidString, 9, "s".into()));
+
+        // An int whose VarInt holds more than 32 bits
+        let wide = [0x0A, 0, 0x03, 1, b'i', 0x80, 0x80, 0x80, 0x80, 0x20, 0];
+        let opts = Options { encoding: Encoding::Network, ..Options::default() };
+        let e = Reader::new(&wide[..], opts).tag().unwrap_err();
+        assert_eq!((e.reason(), e.offset(), e.path().to_string()), (&Reason::Malformed, 5, "i".into()));
+        let expected = parser::parse(&wide, opts).unwrap_err();
+        assert_eq!((expected.reason(), expected.offset()), (&Reason::Malformed, 5));
+    }
+
+    #[test]
+    fn limits() {
+        let mut data = Vec::new();
+        writer::tag(&mut data, &root()).unwrap();
+        let with = |limits| Options { limits, ..Options::default() };
+        let reason = |limits| Reader::new(&data[..], with(limits)).tag().unwrap_err().reason().clone();
+        assert_eq!(reason(Limits { depth: 1, ..Limits::default() }), Reason::DepthLimit);
+        assert_eq!(reason(Limits { length: 2, ..Limits::default() }), Reason::LengthLimit(3));
+        assert_eq!(reason(Limits { bytes: data.len() - 1, ..Limits::default() }), Reason::SizeLimit);
+        assert!(Reader::new(&data[..], with(Limits { bytes: data.len(), ..Limits::default() })).tag().is_ok());
+
+        let mut reader = Reader::new(&data[..], Options::default());
+        reader.header().unwrap();
+        reader.skip(10).unwrap();
+        assert_eq!(reader.offset(), data.len());
+
+        // A huge claimed length fails on the missing data, not on allocation
+        let huge = [0x07, 0, 0, 0x7F, 0xFF, 0xFF, 0xFF];
+        let e = Reader::new(&huge[..], with(Limits::none())).tag().unwrap_err();
+        assert_eq!((e.reason(), e.offset()), (&Reason::Truncated, 7));
+    }
+}
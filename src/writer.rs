@@ -0,0 +1,284 @@
+//! Serialization of `Tag` and `Payload` trees back into NBT.
+//!
+//! Every function mirrors its counterpart in `parser`, so parsing a tree and
+//! writing it again reproduces the input byte for byte.
+
+use std::io::{self, Write};
+
+use crate::mutf8;
+use crate::parser::payload::Payload;
+use crate::parser::tag::Tag;
+use crate::parser::Encoding;
+
+pub fn tag<W: Write>(w: &mut W, tag: &Tag) -> io::Result<()> {
+    tag_with(w, tag, Encoding::default())
+}
+
+pub fn tag_with<W: Write>(w: &mut W, tag: &Tag, enc: Encoding) -> io::Result<()> {
+    if let Payload::End = tag.payload {
+        return Err(invalid("an end payload cannot be named"));
+    }
+    w.write_all(&[tag.payload.id() as u8])?;
+    string(w, &tag.name, enc)?;
+    payload_with(w, &tag.payload, enc)
+}
+
+/// Writes a root tag without a name, as the Java protocol does since 1.20.2.
+pub fn nameless<W: Write>(w: &mut W, payload: &Payload) -> io::Result<()> {
+    nameless_with(w, payload, Encoding::default())
+}
+
+pub fn nameless_with<W: Write>(w: &mut W, payload: &Payload, enc: Encoding) -> io::Result<()> {
+    w.write_all(&[payload.id() as u8])?;
+    payload_with(w, payload, enc)
+}
+
+pub fn end<W: Write>(w: &mut W) -> io::Result<()> {
+    w.write_all(&[0])
+}
+
+pub fn payload<W: Write>(w: &mut W, payload: &Payload) -> io::Result<()> {
+    payload_with(w, payload, Encoding::default())
+}
+
+pub fn payload_with<W: Write>(w: &mut W, payload: &Payload, enc: Encoding) -> io::Result<()> {
+    match payload {
+        Payload::End => Ok(()),
+        Payload::Byte(x) => w.write_all(&x.to_be_bytes()),
+        Payload::Short(x) => enc.write_short(w, *x),
+        Payload::Int(x) => enc.write_int(w, *x),
+        Payload::Long(x) => enc.write_long(w, *x),
+        Payload::Float(x) => enc.write_float(w, *x),
+        Payload::Double(x) => enc.write_double(w, *x),
+        Payload::ByteArray(x) => byte_array(w, x, enc),
+        Payload::String(x) => string(w, x, enc),
+        Payload::List(id, x) => list(w, *id, x, enc),
+        Payload::Compound(x) => compound(w, x, enc),
+        Payload::IntArray(x) => int_array(w, x, enc),
+        Payload::LongArray(x) => long_array(w, x, enc),
+    }
+}
+
+pub fn byte_array<W: Write>(w: &mut W, x: &[u8], enc: Encoding) -> io::Result<()> {
+    enc.write_length(w, x.len())?;
+    w.write_all(x)
+}
+
+/// Java strings are written as Modified UTF-8, Bedrock strings as UTF-8.
+pub fn string<W: Write>(w: &mut W, x: &str, enc: Encoding) -> io::Result<()> {
+    let bytes = match enc {
+        Encoding::BigEndian => mutf8::encode(x),
+        _ => x.as_bytes().into(),
+    };
+    enc.write_string_length(w, bytes.len())?;
+    w.write_all(&bytes)
+}
+
+pub fn list<W: Write>(w: &mut W, id: i8, x: &[Payload], enc: Encoding) -> io::Result<()> {
+    if x.iter().any(|p| p.id() != id) {
+        return Err(invalid("list element does not match the list type"));
+    }
+    w.write_all(&[id as u8])?;
+    enc.write_length(w, x.len())?;
+    x.iter().try_for_each(|p| payload_with(w, p, enc))
+}
+
+pub fn compound<W: Write>(w: &mut W, x: &[Tag], enc: Encoding) -> io::Result<()> {
+    x.iter().try_for_each(|t| tag_with(w, t, enc))?;
+    end(w)
+}
+
+pub fn int_array<W: Write>(w: &mut W, x: &[i32], enc: Encoding) -> io::Result<()> {
+    enc.write_length(w, x.len())?;
+    x.iter().try_for_each(|&i| enc.write_int(w, i))
+}
+
+pub fn long_array<W: Write>(w: &mut W, x: &[i64], enc: Encoding) -> io::Result<()> {
+    enc.write_length(w, x.len())?;
+    x.iter().try_for_each(|&i| enc.write_long(w, i))
+}
+
+impl Encoding {
+    fn write_short<W: Write>(self, w: &mut W, x: i16) -> io::Result<()> {
+        match self {
+            Encoding::BigEndian => w.write_all(&x.to_be_bytes()),
+            Encoding::LittleEndian | Encoding::Network => w.write_all(&x.to_le_bytes()),
+        }
+    }
+
+    fn write_int<W: Write>(self, w: &mut W, x: i32) -> io::Result<()> {
+        match self {
+            Encoding::BigEndian => w.write_all(&x.to_be_bytes()),
+            Encoding::LittleEndian => w.write_all(&x.to_le_bytes()),
+            Encoding::Network => varint(w, zigzag(x as i64)),
+        }
+    }
+
+    fn write_long<W: Write>(self, w: &mut W, x: i64) -> io::Result<()> {
+        match self {
+            Encoding::BigEndian => w.write_all(&x.to_be_bytes()),
+            Encoding::LittleEndian => w.write_all(&x.to_le_bytes()),
+            Encoding::Network => varint(w, zigzag(x)),
+        }
+    }
+
+    fn write_float<W: Write>(self, w: &mut W, x: f32) -> io::Result<()> {
+        match self {
+            Encoding::BigEndian => w.write_all(&x.to_be_bytes()),
+            Encoding::LittleEndian | Encoding::Network => w.write_all(&x.to_le_bytes()),
+        }
+    }
+
+    fn write_double<W: Write>(self, w: &mut W, x: f64) -> io::Result<()> {
+        match self {
+            Encoding::BigEndian => w.write_all(&x.to_be_bytes()),
+            Encoding::LittleEndian | Encoding::Network => w.write_all(&x.to_le_bytes()),
+        }
+    }
+
+    /// Writes the element count of an array or list.
+    fn write_length<W: Write>(self, w: &mut W, len: usize) -> io::Result<()> {
+        let len = i32::try_from(len).map_err(|_| invalid("too many elements"))?;
+        self.write_int(w, len)
+    }
+
+    /// Writes the byte length of a string.
+    fn write_string_length<W: Write>(self, w: &mut W, len: usize) -> io::Result<()> {
+        let too_long = |_| invalid("string is too long");
+        match self {
+            Encoding::BigEndian => w.write_all(&u16::try_from(len).map_err(too_long)?.to_be_bytes()),
+            Encoding::LittleEndian => w.write_all(&u16::try_from(len).map_err(too_long)?.to_le_bytes()),
+            Encoding::Network => varint(w, u32::try_from(len).map_err(too_long)? as u64),
+        }
+    }
+}
+
+/// Writes an unsigned LEB128 VarInt.
+pub fn varint<W: Write>(w: &mut W, mut x: u64) -> io::Result<()> {
+    let mut buf = [0; 10];
+    let mut i = 0;
+    while x >= 0x80 {
+        buf[i] = x as u8 | 0x80;
+        x >>= 7;
+        i += 1;
+    }
+    buf[i] = x as u8;
+    w.write_all(&buf[..=i])
+}
+
+/// ZigZag encodes a signed integer so small magnitudes give short VarInts.
+pub fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::payload::Payload;
+    use crate::parser::tag::{self, Tag};
+    use crate::parser::{Encoding, Options};
+    use crate::writer;
+
+    fn bytes(t: &Tag) -> Vec<u8> {
+        let mut out = Vec::new();
+        writer::tag(&mut out, t).unwrap();
+        out
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(bytes(&Tag::new("b", Payload::Byte(-1))), [1, 0, 1, b'b', 0xff]);
+        assert_eq!(bytes(&Tag::new("s", Payload::Short(0x0123))), [2, 0, 1, b's', 0x01, 0x23]);
+        assert_eq!(bytes(&Tag::new("", Payload::Int(1))), [3, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bytes(&Tag::new("", Payload::Float(1.0))), [5, 0, 0, 0x3f, 0x80, 0, 0]);
+    }
+
+    #[test]
+    fn strings() {
+        let t = Tag::new("\0", Payload::String("\u{1F600}".into()));
+        let out = [8, 0, 2, 0xC0, 0x80, 0, 6, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80];
+        assert_eq!(bytes(&t), out);
+
+        let long = Tag::new("", Payload::String("a".repeat(65536)));
+        assert!(writer::tag(&mut Vec::new(), &long).is_err());
+    }
+
+    #[test]
+    fn lists() {
+        let empty = Tag::new("", Payload::List(10, vec![]));
+        assert_eq!(bytes(&empty), [9, 0, 0, 10, 0, 0, 0, 0]);
+
+        let mixed = Tag::new("", Payload::List(1, vec![Payload::Byte(1), Payload::Short(2)]));
+        assert!(writer::tag(&mut Vec::new(), &mixed).is_err());
+    }
+
+    #[test]
+    fn nameless() {
+        let mut out = Vec::new();
+        writer::nameless(&mut out, &Payload::String("hi".into())).unwrap();
+        writer::nameless(&mut out, &Payload::End).unwrap();
+        assert_eq!(out, [8, 0, 2, b'h', b'i', 0]);
+    }
+
+    #[test]
+    fn little_endian() {
+        let t = Tag::new("é", Payload::list(vec![Payload::Int(1), Payload::Int(2)]));
+        let mut out = Vec::new();
+        writer::tag_with(&mut out, &t, Encoding::LittleEndian).unwrap();
+        assert_eq!(out, [9, 2, 0, 0xC3, 0xA9, 3, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0]);
+
+        let le = Options { encoding: Encoding::LittleEndian, ..Options::default() };
+        assert_eq!(tag::tag_with(le)(&out), Ok((&[] as &[u8], t)));
+    }
+
+    #[test]
+    fn network() {
+        let t = Tag::new("", Payload::Compound(vec![
+            Tag::new("i", Payload::Int(-2)),
+            Tag::new("l", Payload::Long(150)),
+            Tag::new("a", Payload::IntArray(vec![1, -1])),
+            Tag::new("s", Payload::String("x".repeat(200))),
+        ]));
+        let mut out = Vec::new();
+        writer::tag_with(&mut out, &t, Encoding::Network).unwrap();
+        assert_eq!(out[..16], [10, 0, 3, 1, b'i', 3, 4, 1, b'l', 0xAC, 0x02, 11, 1, b'a', 4, 2]);
+        assert_eq!(out[16..23], [1, 8, 1, b's', 0xC8, 0x01, b'x']);
+
+        let net = Options { encoding: Encoding::Network, ..Options::default() };
+        assert_eq!(tag::tag_with(net)(&out), Ok((&[] as &[u8], t)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let root = Tag::new("root", Payload::Compound(vec![
+            Tag::new("byte", Payload::Byte(-2)),
+            Tag::new("short", Payload::Short(-300)),
+            Tag::new("int", Payload::Int(1 << 20)),
+            Tag::new("long", Payload::Long(-1 << 40)),
+            Tag::new("float", Payload::Float(f32::from_bits(0x7fc0_0001))),
+            Tag::new("double", Payload::Double(-0.0)),
+            Tag::new("bytes", Payload::ByteArray(vec![1, 2, 3])),
+            Tag::new("string", Payload::String("caf\u{e9} \u{1F600}".into())),
+            Tag::new("empty", Payload::List(8, vec![])),
+            Tag::new("nested", Payload::list(vec![
+                Payload::list(vec![Payload::Int(1)]),
+                Payload::List(0, vec![]),
+            ])),
+            Tag::new("compounds", Payload::list(vec![
+                Payload::Compound(vec![]),
+                Payload::Compound(vec![Tag::new("x", Payload::Byte(1))]),
+            ])),
+            Tag::new("ints", Payload::IntArray(vec![i32::MIN, 0, i32::MAX])),
+            Tag::new("longs", Payload::LongArray(vec![i64::MIN, 0, i64::MAX])),
+        ]));
+
+        let out = bytes(&root);
+        let (rest, parsed) = tag::tag(&out).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(bytes(&parsed), out);
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", root));
+    }
+}